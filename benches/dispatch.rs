@@ -0,0 +1,43 @@
+// Measures raw instruction dispatch speed on a small loop kept in memory
+// run with `cargo bench --bench dispatch`
+//
+// baseline, this loop in release on the same machine:
+//   HashMap dispatch cloning the Instruction on every step, the commit before the
+//   opcode tables with its opcode printing removed: ~21 M instructions/s
+//   opcode tables, the commit that added them: ~93 M instructions/s
+// the timer, ppu and dma now run on every M-cycle, so today's figure (~35 M instructions/s)
+// measures more than dispatch and is not comparable with the two above
+use std::time::Instant;
+
+use my_bg_rust::cpu::CPU;
+
+const STEPS: u64 = 20_000_000;
+
+fn main() {
+    #[rustfmt::skip]
+    let program = [
+        0x3E, 0x00,       // LD A, $00
+        0x06, 0xFF,       // LD B, $FF
+        0x80,             // ADD A, B
+        0xCB, 0x37,       // SWAP A
+        0x05,             // DEC B
+        0x20, 0xFA,       // JR NZ, $0004
+        0xC3, 0x02, 0x00, // JP $0002
+    ];
+
+    let mut cpu = CPU::new();
+    cpu.memory.load_rom(&program);
+
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        cpu.execute_instruction().unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{} instructions in {:?}: {:.1} M instructions/s",
+        STEPS,
+        elapsed,
+        STEPS as f64 / elapsed.as_secs_f64() / 1_000_000.0
+    );
+}
@@ -0,0 +1,75 @@
+use crate::interrupts::{Interrupt, IE_ADDRESS, IF_ADDRESS};
+
+// everything the cpu sees of the machine: the 16 bits address space and the clock
+// driving the other components. Memory is the real one, tests can plug in simpler ones
+pub trait Bus: 'static {
+    // reads have no side effect, they are also used by traces and the decoder
+    fn read(&self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, data: u8);
+
+    // advances every other component by one M-cycle (4 cycles),
+    // returns true when the ppu enters VBlank
+    fn tick(&mut self) -> bool {
+        false
+    }
+
+    // rom bank mapped at the given address, used in diagnostics
+    fn rom_bank(&self, _address: u16) -> u16 {
+        0
+    }
+
+    // called by peripherals to raise an interrupt in IF
+    fn request_interrupt(&mut self, interrupt: Interrupt) {
+        let requested = self.read(IF_ADDRESS as u16);
+        self.write(IF_ADDRESS as u16, requested | interrupt.bit());
+    }
+
+    fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        let requested = self.read(IF_ADDRESS as u16);
+        self.write(IF_ADDRESS as u16, requested & !interrupt.bit());
+    }
+
+    fn requested_interrupts(&self) -> u8 {
+        self.read(IF_ADDRESS as u16) & 0x1F
+    }
+
+    // interrupts both requested and enabled, regardless of IME
+    fn pending_interrupts(&self) -> u8 {
+        self.requested_interrupts() & self.read(IE_ADDRESS as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FlatBus([u8; 0x10000]);
+
+    impl Bus for FlatBus {
+        fn read(&self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.0[address as usize] = data;
+        }
+    }
+
+    #[test]
+    fn interrupt_helpers_go_through_if_and_ie() {
+        let mut bus = FlatBus([0; 0x10000]);
+        bus.write(IF_ADDRESS as u16, 0xE0);
+        bus.request_interrupt(Interrupt::Timer);
+        bus.request_interrupt(Interrupt::VBlank);
+        assert_eq!(bus.read(IF_ADDRESS as u16), 0xE5);
+        assert_eq!(bus.requested_interrupts(), 0x05);
+        assert_eq!(bus.pending_interrupts(), 0x00);
+
+        bus.write(IE_ADDRESS as u16, Interrupt::Timer.bit());
+        assert_eq!(bus.pending_interrupts(), 0x04);
+        bus.clear_interrupt(Interrupt::Timer);
+        assert_eq!(bus.pending_interrupts(), 0x00);
+        assert_eq!(bus.requested_interrupts(), 0x01);
+    }
+}
@@ -0,0 +1,221 @@
+use super::{Reg16, CPU};
+use crate::bus::Bus;
+
+// 8 bits arithmetic and logic unit
+// every operation works on the accumulator (a) and updates the flags
+impl<B: Bus> CPU<B> {
+    pub fn read_hl(&mut self) -> u8 {
+        let hl = self.registers.get_register_value_u16(Reg16::HL);
+        self.read_cycle(hl)
+    }
+
+    pub fn write_hl(&mut self, value: u8) {
+        let hl = self.registers.get_register_value_u16(Reg16::HL);
+        self.write_cycle(hl, value);
+    }
+
+    fn set_flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.registers.f.set_z(z);
+        self.registers.f.set_n(n);
+        self.registers.f.set_h(h);
+        self.registers.f.set_c(c);
+    }
+
+    pub fn add(&mut self, value: u8) {
+        self.add_with_carry(value, false);
+    }
+
+    pub fn adc(&mut self, value: u8) {
+        self.add_with_carry(value, self.registers.f.c());
+    }
+
+    fn add_with_carry(&mut self, value: u8, carry: bool) {
+        let carry = carry as u8;
+        let result = self.registers.a as u16 + value as u16 + carry as u16;
+        let half_carry = (self.registers.a & 0x0F) + (value & 0x0F) + carry > 0x0F;
+        self.registers.a = result as u8;
+        self.set_flags(self.registers.a == 0, false, half_carry, result > 0xFF);
+    }
+
+    pub fn sub(&mut self, value: u8) {
+        self.registers.a = self.sub_with_carry(value, false);
+    }
+
+    pub fn sbc(&mut self, value: u8) {
+        self.registers.a = self.sub_with_carry(value, self.registers.f.c());
+    }
+
+    // CP is a SUB whose result is thrown away
+    pub fn cp(&mut self, value: u8) {
+        self.sub_with_carry(value, false);
+    }
+
+    fn sub_with_carry(&mut self, value: u8, carry: bool) -> u8 {
+        let carry = carry as u8;
+        let result = self.registers.a.wrapping_sub(value).wrapping_sub(carry);
+        let half_carry = (self.registers.a & 0x0F) < (value & 0x0F) + carry;
+        let full_carry = (self.registers.a as u16) < value as u16 + carry as u16;
+        self.set_flags(result == 0, true, half_carry, full_carry);
+        result
+    }
+
+    pub fn and(&mut self, value: u8) {
+        self.registers.a &= value;
+        self.set_flags(self.registers.a == 0, false, true, false);
+    }
+
+    pub fn or(&mut self, value: u8) {
+        self.registers.a |= value;
+        self.set_flags(self.registers.a == 0, false, false, false);
+    }
+
+    pub fn xor(&mut self, value: u8) {
+        self.registers.a ^= value;
+        self.set_flags(self.registers.a == 0, false, false, false);
+    }
+
+    // INC and DEC leave the carry flag untouched
+    pub fn inc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.registers.f.set_z(result == 0);
+        self.registers.f.set_n(false);
+        self.registers.f.set_h(value & 0x0F == 0x0F);
+        result
+    }
+
+    pub fn dec(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.registers.f.set_z(result == 0);
+        self.registers.f.set_n(true);
+        self.registers.f.set_h(value & 0x0F == 0x00);
+        result
+    }
+
+    // decimal adjust after a BCD addition or subtraction, N tells which one it was
+    pub fn daa(&mut self) {
+        let mut adjust = 0;
+        let mut carry = false;
+        if self.registers.f.h() || (!self.registers.f.n() && self.registers.a & 0x0F > 0x09) {
+            adjust |= 0x06;
+        }
+        if self.registers.f.c() || (!self.registers.f.n() && self.registers.a > 0x99) {
+            adjust |= 0x60;
+            carry = true;
+        }
+
+        self.registers.a = if self.registers.f.n() {
+            self.registers.a.wrapping_sub(adjust)
+        } else {
+            self.registers.a.wrapping_add(adjust)
+        };
+        self.registers.f.set_z(self.registers.a == 0);
+        self.registers.f.set_h(false);
+        self.registers.f.set_c(carry);
+    }
+}
+
+// 16 bits arithmetic
+impl<B: Bus> CPU<B> {
+    // Z is left untouched, H and C come from bits 11 and 15
+    pub fn add_hl(&mut self, value: u16) {
+        self.internal_cycle();
+        let hl = self.registers.get_register_value_u16(Reg16::HL);
+        let (result, carry) = hl.overflowing_add(value);
+        self.registers.f.set_n(false);
+        self.registers
+            .f
+            .set_h((hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF);
+        self.registers.f.set_c(carry);
+        self.registers.set_register_value_u16(Reg16::HL, result);
+    }
+
+    // shared by ADD SP, n and LDHL SP, n: the operand is a signed byte but
+    // H and C are computed as an unsigned add on the low byte of SP
+    pub fn sp_plus_signed_operand(&mut self) -> u16 {
+        let n = self.read_next_opcode();
+        let sp = self.registers.get_register_value_u16(Reg16::SP);
+        self.set_flags(
+            false,
+            false,
+            (sp & 0x000F) + (n as u16 & 0x000F) > 0x000F,
+            (sp & 0x00FF) + n as u16 > 0x00FF,
+        );
+        sp.wrapping_add(n as i8 as u16)
+    }
+
+    // 16 bits INC and DEC do not touch any flag, but take an extra cycle
+    pub fn inc_u16(&mut self, register: Reg16) {
+        self.internal_cycle();
+        let value = self.registers.get_register_value_u16(register);
+        self.registers
+            .set_register_value_u16(register, value.wrapping_add(1));
+    }
+
+    pub fn dec_u16(&mut self, register: Reg16) {
+        self.internal_cycle();
+        let value = self.registers.get_register_value_u16(register);
+        self.registers
+            .set_register_value_u16(register, value.wrapping_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::test_cpu;
+
+    // runs `LD A, a ; <op> A, b ; DAA` and returns A and F
+    fn daa_after(op_code: u8, a: u8, b: u8) -> (u8, u8) {
+        let mut cpu = test_cpu(&[0x3E, a, op_code, b, 0x27]);
+        cpu.run_for_cycles(20).unwrap();
+        (cpu.registers.a, cpu.registers.f.bits())
+    }
+
+    #[test]
+    fn daa_adjusts_bcd_additions() {
+        // ADD A, n
+        assert_eq!(daa_after(0xC6, 0x15, 0x27), (0x42, 0x00));
+        assert_eq!(daa_after(0xC6, 0x99, 0x01), (0x00, 0x90));
+        assert_eq!(daa_after(0xC6, 0x50, 0x70), (0x20, 0x10));
+    }
+
+    #[test]
+    fn daa_adjusts_bcd_subtractions() {
+        // SUB n
+        assert_eq!(daa_after(0xD6, 0x42, 0x15), (0x27, 0x40));
+        assert_eq!(daa_after(0xD6, 0x00, 0x01), (0x99, 0x50));
+        assert_eq!(daa_after(0xD6, 0x15, 0x15), (0x00, 0xC0));
+    }
+
+    #[test]
+    fn cpl_scf_and_ccf_only_touch_their_flags() {
+        // CPL ; SCF ; CCF
+        let mut cpu = test_cpu(&[0x2F, 0x37, 0x3F]);
+        cpu.registers.a = 0x35;
+        cpu.registers.f.set_z(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0xCA);
+        assert_eq!(cpu.registers.f.bits(), 0xE0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.f.bits(), 0x90);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.f.bits(), 0x80);
+    }
+
+    #[test]
+    fn accumulator_rotates_always_clear_z() {
+        // RLCA ; RLA ; RRCA ; RRA
+        let mut cpu = test_cpu(&[0x07, 0x17, 0x0F, 0x1F]);
+        cpu.registers.a = 0x80;
+        cpu.step().unwrap();
+        assert_eq!((cpu.registers.a, cpu.registers.f.bits()), (0x01, 0x10));
+        cpu.registers.a = 0x80;
+        cpu.registers.f.set_c(false);
+        cpu.step().unwrap();
+        assert_eq!((cpu.registers.a, cpu.registers.f.bits()), (0x00, 0x10));
+        cpu.registers.a = 0x01;
+        cpu.step().unwrap();
+        assert_eq!((cpu.registers.a, cpu.registers.f.bits()), (0x80, 0x10));
+        cpu.step().unwrap();
+        assert_eq!((cpu.registers.a, cpu.registers.f.bits()), (0xC0, 0x00));
+    }
+}
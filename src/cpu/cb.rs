@@ -0,0 +1,896 @@
+use super::instruction::InstructionSet;
+use super::CPU;
+use crate::bus::Bus;
+
+// CB prefixed instructions: rotates, shifts, SWAP and single bit operations
+pub fn register_cb_instructions<B: Bus>(set: &mut InstructionSet<B>) {
+    // RLC n
+    set.store_cb_instructions(0x00, 8, "RLC B", |cpu| {
+        cpu.registers.b = cpu.rlc(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x01, 8, "RLC C", |cpu| {
+        cpu.registers.c = cpu.rlc(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x02, 8, "RLC D", |cpu| {
+        cpu.registers.d = cpu.rlc(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x03, 8, "RLC E", |cpu| {
+        cpu.registers.e = cpu.rlc(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x04, 8, "RLC H", |cpu| {
+        cpu.registers.h = cpu.rlc(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x05, 8, "RLC L", |cpu| {
+        cpu.registers.l = cpu.rlc(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x06, 16, "RLC (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.rlc(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x07, 8, "RLC A", |cpu| {
+        cpu.registers.a = cpu.rlc(cpu.registers.a)
+    });
+
+    // RRC n
+    set.store_cb_instructions(0x08, 8, "RRC B", |cpu| {
+        cpu.registers.b = cpu.rrc(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x09, 8, "RRC C", |cpu| {
+        cpu.registers.c = cpu.rrc(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x0A, 8, "RRC D", |cpu| {
+        cpu.registers.d = cpu.rrc(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x0B, 8, "RRC E", |cpu| {
+        cpu.registers.e = cpu.rrc(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x0C, 8, "RRC H", |cpu| {
+        cpu.registers.h = cpu.rrc(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x0D, 8, "RRC L", |cpu| {
+        cpu.registers.l = cpu.rrc(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x0E, 16, "RRC (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.rrc(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x0F, 8, "RRC A", |cpu| {
+        cpu.registers.a = cpu.rrc(cpu.registers.a)
+    });
+
+    // RL n
+    set.store_cb_instructions(0x10, 8, "RL B", |cpu| {
+        cpu.registers.b = cpu.rl(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x11, 8, "RL C", |cpu| {
+        cpu.registers.c = cpu.rl(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x12, 8, "RL D", |cpu| {
+        cpu.registers.d = cpu.rl(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x13, 8, "RL E", |cpu| {
+        cpu.registers.e = cpu.rl(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x14, 8, "RL H", |cpu| {
+        cpu.registers.h = cpu.rl(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x15, 8, "RL L", |cpu| {
+        cpu.registers.l = cpu.rl(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x16, 16, "RL (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.rl(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x17, 8, "RL A", |cpu| {
+        cpu.registers.a = cpu.rl(cpu.registers.a)
+    });
+
+    // RR n
+    set.store_cb_instructions(0x18, 8, "RR B", |cpu| {
+        cpu.registers.b = cpu.rr(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x19, 8, "RR C", |cpu| {
+        cpu.registers.c = cpu.rr(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x1A, 8, "RR D", |cpu| {
+        cpu.registers.d = cpu.rr(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x1B, 8, "RR E", |cpu| {
+        cpu.registers.e = cpu.rr(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x1C, 8, "RR H", |cpu| {
+        cpu.registers.h = cpu.rr(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x1D, 8, "RR L", |cpu| {
+        cpu.registers.l = cpu.rr(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x1E, 16, "RR (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.rr(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x1F, 8, "RR A", |cpu| {
+        cpu.registers.a = cpu.rr(cpu.registers.a)
+    });
+
+    // SLA n
+    set.store_cb_instructions(0x20, 8, "SLA B", |cpu| {
+        cpu.registers.b = cpu.sla(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x21, 8, "SLA C", |cpu| {
+        cpu.registers.c = cpu.sla(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x22, 8, "SLA D", |cpu| {
+        cpu.registers.d = cpu.sla(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x23, 8, "SLA E", |cpu| {
+        cpu.registers.e = cpu.sla(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x24, 8, "SLA H", |cpu| {
+        cpu.registers.h = cpu.sla(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x25, 8, "SLA L", |cpu| {
+        cpu.registers.l = cpu.sla(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x26, 16, "SLA (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.sla(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x27, 8, "SLA A", |cpu| {
+        cpu.registers.a = cpu.sla(cpu.registers.a)
+    });
+
+    // SRA n
+    set.store_cb_instructions(0x28, 8, "SRA B", |cpu| {
+        cpu.registers.b = cpu.sra(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x29, 8, "SRA C", |cpu| {
+        cpu.registers.c = cpu.sra(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x2A, 8, "SRA D", |cpu| {
+        cpu.registers.d = cpu.sra(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x2B, 8, "SRA E", |cpu| {
+        cpu.registers.e = cpu.sra(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x2C, 8, "SRA H", |cpu| {
+        cpu.registers.h = cpu.sra(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x2D, 8, "SRA L", |cpu| {
+        cpu.registers.l = cpu.sra(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x2E, 16, "SRA (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.sra(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x2F, 8, "SRA A", |cpu| {
+        cpu.registers.a = cpu.sra(cpu.registers.a)
+    });
+
+    // SWAP n
+    set.store_cb_instructions(0x30, 8, "SWAP B", |cpu| {
+        cpu.registers.b = cpu.swap(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x31, 8, "SWAP C", |cpu| {
+        cpu.registers.c = cpu.swap(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x32, 8, "SWAP D", |cpu| {
+        cpu.registers.d = cpu.swap(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x33, 8, "SWAP E", |cpu| {
+        cpu.registers.e = cpu.swap(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x34, 8, "SWAP H", |cpu| {
+        cpu.registers.h = cpu.swap(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x35, 8, "SWAP L", |cpu| {
+        cpu.registers.l = cpu.swap(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x36, 16, "SWAP (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.swap(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x37, 8, "SWAP A", |cpu| {
+        cpu.registers.a = cpu.swap(cpu.registers.a)
+    });
+
+    // SRL n
+    set.store_cb_instructions(0x38, 8, "SRL B", |cpu| {
+        cpu.registers.b = cpu.srl(cpu.registers.b)
+    });
+    set.store_cb_instructions(0x39, 8, "SRL C", |cpu| {
+        cpu.registers.c = cpu.srl(cpu.registers.c)
+    });
+    set.store_cb_instructions(0x3A, 8, "SRL D", |cpu| {
+        cpu.registers.d = cpu.srl(cpu.registers.d)
+    });
+    set.store_cb_instructions(0x3B, 8, "SRL E", |cpu| {
+        cpu.registers.e = cpu.srl(cpu.registers.e)
+    });
+    set.store_cb_instructions(0x3C, 8, "SRL H", |cpu| {
+        cpu.registers.h = cpu.srl(cpu.registers.h)
+    });
+    set.store_cb_instructions(0x3D, 8, "SRL L", |cpu| {
+        cpu.registers.l = cpu.srl(cpu.registers.l)
+    });
+    set.store_cb_instructions(0x3E, 16, "SRL (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.srl(value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x3F, 8, "SRL A", |cpu| {
+        cpu.registers.a = cpu.srl(cpu.registers.a)
+    });
+
+    // BIT b, r
+    set.store_cb_instructions(0x40, 8, "BIT 0, B", |cpu| cpu.bit(0, cpu.registers.b));
+    set.store_cb_instructions(0x41, 8, "BIT 0, C", |cpu| cpu.bit(0, cpu.registers.c));
+    set.store_cb_instructions(0x42, 8, "BIT 0, D", |cpu| cpu.bit(0, cpu.registers.d));
+    set.store_cb_instructions(0x43, 8, "BIT 0, E", |cpu| cpu.bit(0, cpu.registers.e));
+    set.store_cb_instructions(0x44, 8, "BIT 0, H", |cpu| cpu.bit(0, cpu.registers.h));
+    set.store_cb_instructions(0x45, 8, "BIT 0, L", |cpu| cpu.bit(0, cpu.registers.l));
+    set.store_cb_instructions(0x46, 12, "BIT 0, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(0, value);
+    });
+    set.store_cb_instructions(0x47, 8, "BIT 0, A", |cpu| cpu.bit(0, cpu.registers.a));
+    set.store_cb_instructions(0x48, 8, "BIT 1, B", |cpu| cpu.bit(1, cpu.registers.b));
+    set.store_cb_instructions(0x49, 8, "BIT 1, C", |cpu| cpu.bit(1, cpu.registers.c));
+    set.store_cb_instructions(0x4A, 8, "BIT 1, D", |cpu| cpu.bit(1, cpu.registers.d));
+    set.store_cb_instructions(0x4B, 8, "BIT 1, E", |cpu| cpu.bit(1, cpu.registers.e));
+    set.store_cb_instructions(0x4C, 8, "BIT 1, H", |cpu| cpu.bit(1, cpu.registers.h));
+    set.store_cb_instructions(0x4D, 8, "BIT 1, L", |cpu| cpu.bit(1, cpu.registers.l));
+    set.store_cb_instructions(0x4E, 12, "BIT 1, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(1, value);
+    });
+    set.store_cb_instructions(0x4F, 8, "BIT 1, A", |cpu| cpu.bit(1, cpu.registers.a));
+    set.store_cb_instructions(0x50, 8, "BIT 2, B", |cpu| cpu.bit(2, cpu.registers.b));
+    set.store_cb_instructions(0x51, 8, "BIT 2, C", |cpu| cpu.bit(2, cpu.registers.c));
+    set.store_cb_instructions(0x52, 8, "BIT 2, D", |cpu| cpu.bit(2, cpu.registers.d));
+    set.store_cb_instructions(0x53, 8, "BIT 2, E", |cpu| cpu.bit(2, cpu.registers.e));
+    set.store_cb_instructions(0x54, 8, "BIT 2, H", |cpu| cpu.bit(2, cpu.registers.h));
+    set.store_cb_instructions(0x55, 8, "BIT 2, L", |cpu| cpu.bit(2, cpu.registers.l));
+    set.store_cb_instructions(0x56, 12, "BIT 2, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(2, value);
+    });
+    set.store_cb_instructions(0x57, 8, "BIT 2, A", |cpu| cpu.bit(2, cpu.registers.a));
+    set.store_cb_instructions(0x58, 8, "BIT 3, B", |cpu| cpu.bit(3, cpu.registers.b));
+    set.store_cb_instructions(0x59, 8, "BIT 3, C", |cpu| cpu.bit(3, cpu.registers.c));
+    set.store_cb_instructions(0x5A, 8, "BIT 3, D", |cpu| cpu.bit(3, cpu.registers.d));
+    set.store_cb_instructions(0x5B, 8, "BIT 3, E", |cpu| cpu.bit(3, cpu.registers.e));
+    set.store_cb_instructions(0x5C, 8, "BIT 3, H", |cpu| cpu.bit(3, cpu.registers.h));
+    set.store_cb_instructions(0x5D, 8, "BIT 3, L", |cpu| cpu.bit(3, cpu.registers.l));
+    set.store_cb_instructions(0x5E, 12, "BIT 3, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(3, value);
+    });
+    set.store_cb_instructions(0x5F, 8, "BIT 3, A", |cpu| cpu.bit(3, cpu.registers.a));
+    set.store_cb_instructions(0x60, 8, "BIT 4, B", |cpu| cpu.bit(4, cpu.registers.b));
+    set.store_cb_instructions(0x61, 8, "BIT 4, C", |cpu| cpu.bit(4, cpu.registers.c));
+    set.store_cb_instructions(0x62, 8, "BIT 4, D", |cpu| cpu.bit(4, cpu.registers.d));
+    set.store_cb_instructions(0x63, 8, "BIT 4, E", |cpu| cpu.bit(4, cpu.registers.e));
+    set.store_cb_instructions(0x64, 8, "BIT 4, H", |cpu| cpu.bit(4, cpu.registers.h));
+    set.store_cb_instructions(0x65, 8, "BIT 4, L", |cpu| cpu.bit(4, cpu.registers.l));
+    set.store_cb_instructions(0x66, 12, "BIT 4, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(4, value);
+    });
+    set.store_cb_instructions(0x67, 8, "BIT 4, A", |cpu| cpu.bit(4, cpu.registers.a));
+    set.store_cb_instructions(0x68, 8, "BIT 5, B", |cpu| cpu.bit(5, cpu.registers.b));
+    set.store_cb_instructions(0x69, 8, "BIT 5, C", |cpu| cpu.bit(5, cpu.registers.c));
+    set.store_cb_instructions(0x6A, 8, "BIT 5, D", |cpu| cpu.bit(5, cpu.registers.d));
+    set.store_cb_instructions(0x6B, 8, "BIT 5, E", |cpu| cpu.bit(5, cpu.registers.e));
+    set.store_cb_instructions(0x6C, 8, "BIT 5, H", |cpu| cpu.bit(5, cpu.registers.h));
+    set.store_cb_instructions(0x6D, 8, "BIT 5, L", |cpu| cpu.bit(5, cpu.registers.l));
+    set.store_cb_instructions(0x6E, 12, "BIT 5, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(5, value);
+    });
+    set.store_cb_instructions(0x6F, 8, "BIT 5, A", |cpu| cpu.bit(5, cpu.registers.a));
+    set.store_cb_instructions(0x70, 8, "BIT 6, B", |cpu| cpu.bit(6, cpu.registers.b));
+    set.store_cb_instructions(0x71, 8, "BIT 6, C", |cpu| cpu.bit(6, cpu.registers.c));
+    set.store_cb_instructions(0x72, 8, "BIT 6, D", |cpu| cpu.bit(6, cpu.registers.d));
+    set.store_cb_instructions(0x73, 8, "BIT 6, E", |cpu| cpu.bit(6, cpu.registers.e));
+    set.store_cb_instructions(0x74, 8, "BIT 6, H", |cpu| cpu.bit(6, cpu.registers.h));
+    set.store_cb_instructions(0x75, 8, "BIT 6, L", |cpu| cpu.bit(6, cpu.registers.l));
+    set.store_cb_instructions(0x76, 12, "BIT 6, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(6, value);
+    });
+    set.store_cb_instructions(0x77, 8, "BIT 6, A", |cpu| cpu.bit(6, cpu.registers.a));
+    set.store_cb_instructions(0x78, 8, "BIT 7, B", |cpu| cpu.bit(7, cpu.registers.b));
+    set.store_cb_instructions(0x79, 8, "BIT 7, C", |cpu| cpu.bit(7, cpu.registers.c));
+    set.store_cb_instructions(0x7A, 8, "BIT 7, D", |cpu| cpu.bit(7, cpu.registers.d));
+    set.store_cb_instructions(0x7B, 8, "BIT 7, E", |cpu| cpu.bit(7, cpu.registers.e));
+    set.store_cb_instructions(0x7C, 8, "BIT 7, H", |cpu| cpu.bit(7, cpu.registers.h));
+    set.store_cb_instructions(0x7D, 8, "BIT 7, L", |cpu| cpu.bit(7, cpu.registers.l));
+    set.store_cb_instructions(0x7E, 12, "BIT 7, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.bit(7, value);
+    });
+    set.store_cb_instructions(0x7F, 8, "BIT 7, A", |cpu| cpu.bit(7, cpu.registers.a));
+
+    // RES b, r
+    set.store_cb_instructions(0x80, 8, "RES 0, B", |cpu| {
+        cpu.registers.b = cpu.res(0, cpu.registers.b)
+    });
+    set.store_cb_instructions(0x81, 8, "RES 0, C", |cpu| {
+        cpu.registers.c = cpu.res(0, cpu.registers.c)
+    });
+    set.store_cb_instructions(0x82, 8, "RES 0, D", |cpu| {
+        cpu.registers.d = cpu.res(0, cpu.registers.d)
+    });
+    set.store_cb_instructions(0x83, 8, "RES 0, E", |cpu| {
+        cpu.registers.e = cpu.res(0, cpu.registers.e)
+    });
+    set.store_cb_instructions(0x84, 8, "RES 0, H", |cpu| {
+        cpu.registers.h = cpu.res(0, cpu.registers.h)
+    });
+    set.store_cb_instructions(0x85, 8, "RES 0, L", |cpu| {
+        cpu.registers.l = cpu.res(0, cpu.registers.l)
+    });
+    set.store_cb_instructions(0x86, 16, "RES 0, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(0, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x87, 8, "RES 0, A", |cpu| {
+        cpu.registers.a = cpu.res(0, cpu.registers.a)
+    });
+    set.store_cb_instructions(0x88, 8, "RES 1, B", |cpu| {
+        cpu.registers.b = cpu.res(1, cpu.registers.b)
+    });
+    set.store_cb_instructions(0x89, 8, "RES 1, C", |cpu| {
+        cpu.registers.c = cpu.res(1, cpu.registers.c)
+    });
+    set.store_cb_instructions(0x8A, 8, "RES 1, D", |cpu| {
+        cpu.registers.d = cpu.res(1, cpu.registers.d)
+    });
+    set.store_cb_instructions(0x8B, 8, "RES 1, E", |cpu| {
+        cpu.registers.e = cpu.res(1, cpu.registers.e)
+    });
+    set.store_cb_instructions(0x8C, 8, "RES 1, H", |cpu| {
+        cpu.registers.h = cpu.res(1, cpu.registers.h)
+    });
+    set.store_cb_instructions(0x8D, 8, "RES 1, L", |cpu| {
+        cpu.registers.l = cpu.res(1, cpu.registers.l)
+    });
+    set.store_cb_instructions(0x8E, 16, "RES 1, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(1, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x8F, 8, "RES 1, A", |cpu| {
+        cpu.registers.a = cpu.res(1, cpu.registers.a)
+    });
+    set.store_cb_instructions(0x90, 8, "RES 2, B", |cpu| {
+        cpu.registers.b = cpu.res(2, cpu.registers.b)
+    });
+    set.store_cb_instructions(0x91, 8, "RES 2, C", |cpu| {
+        cpu.registers.c = cpu.res(2, cpu.registers.c)
+    });
+    set.store_cb_instructions(0x92, 8, "RES 2, D", |cpu| {
+        cpu.registers.d = cpu.res(2, cpu.registers.d)
+    });
+    set.store_cb_instructions(0x93, 8, "RES 2, E", |cpu| {
+        cpu.registers.e = cpu.res(2, cpu.registers.e)
+    });
+    set.store_cb_instructions(0x94, 8, "RES 2, H", |cpu| {
+        cpu.registers.h = cpu.res(2, cpu.registers.h)
+    });
+    set.store_cb_instructions(0x95, 8, "RES 2, L", |cpu| {
+        cpu.registers.l = cpu.res(2, cpu.registers.l)
+    });
+    set.store_cb_instructions(0x96, 16, "RES 2, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(2, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x97, 8, "RES 2, A", |cpu| {
+        cpu.registers.a = cpu.res(2, cpu.registers.a)
+    });
+    set.store_cb_instructions(0x98, 8, "RES 3, B", |cpu| {
+        cpu.registers.b = cpu.res(3, cpu.registers.b)
+    });
+    set.store_cb_instructions(0x99, 8, "RES 3, C", |cpu| {
+        cpu.registers.c = cpu.res(3, cpu.registers.c)
+    });
+    set.store_cb_instructions(0x9A, 8, "RES 3, D", |cpu| {
+        cpu.registers.d = cpu.res(3, cpu.registers.d)
+    });
+    set.store_cb_instructions(0x9B, 8, "RES 3, E", |cpu| {
+        cpu.registers.e = cpu.res(3, cpu.registers.e)
+    });
+    set.store_cb_instructions(0x9C, 8, "RES 3, H", |cpu| {
+        cpu.registers.h = cpu.res(3, cpu.registers.h)
+    });
+    set.store_cb_instructions(0x9D, 8, "RES 3, L", |cpu| {
+        cpu.registers.l = cpu.res(3, cpu.registers.l)
+    });
+    set.store_cb_instructions(0x9E, 16, "RES 3, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(3, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0x9F, 8, "RES 3, A", |cpu| {
+        cpu.registers.a = cpu.res(3, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xA0, 8, "RES 4, B", |cpu| {
+        cpu.registers.b = cpu.res(4, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xA1, 8, "RES 4, C", |cpu| {
+        cpu.registers.c = cpu.res(4, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xA2, 8, "RES 4, D", |cpu| {
+        cpu.registers.d = cpu.res(4, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xA3, 8, "RES 4, E", |cpu| {
+        cpu.registers.e = cpu.res(4, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xA4, 8, "RES 4, H", |cpu| {
+        cpu.registers.h = cpu.res(4, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xA5, 8, "RES 4, L", |cpu| {
+        cpu.registers.l = cpu.res(4, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xA6, 16, "RES 4, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(4, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xA7, 8, "RES 4, A", |cpu| {
+        cpu.registers.a = cpu.res(4, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xA8, 8, "RES 5, B", |cpu| {
+        cpu.registers.b = cpu.res(5, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xA9, 8, "RES 5, C", |cpu| {
+        cpu.registers.c = cpu.res(5, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xAA, 8, "RES 5, D", |cpu| {
+        cpu.registers.d = cpu.res(5, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xAB, 8, "RES 5, E", |cpu| {
+        cpu.registers.e = cpu.res(5, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xAC, 8, "RES 5, H", |cpu| {
+        cpu.registers.h = cpu.res(5, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xAD, 8, "RES 5, L", |cpu| {
+        cpu.registers.l = cpu.res(5, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xAE, 16, "RES 5, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(5, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xAF, 8, "RES 5, A", |cpu| {
+        cpu.registers.a = cpu.res(5, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xB0, 8, "RES 6, B", |cpu| {
+        cpu.registers.b = cpu.res(6, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xB1, 8, "RES 6, C", |cpu| {
+        cpu.registers.c = cpu.res(6, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xB2, 8, "RES 6, D", |cpu| {
+        cpu.registers.d = cpu.res(6, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xB3, 8, "RES 6, E", |cpu| {
+        cpu.registers.e = cpu.res(6, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xB4, 8, "RES 6, H", |cpu| {
+        cpu.registers.h = cpu.res(6, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xB5, 8, "RES 6, L", |cpu| {
+        cpu.registers.l = cpu.res(6, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xB6, 16, "RES 6, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(6, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xB7, 8, "RES 6, A", |cpu| {
+        cpu.registers.a = cpu.res(6, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xB8, 8, "RES 7, B", |cpu| {
+        cpu.registers.b = cpu.res(7, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xB9, 8, "RES 7, C", |cpu| {
+        cpu.registers.c = cpu.res(7, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xBA, 8, "RES 7, D", |cpu| {
+        cpu.registers.d = cpu.res(7, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xBB, 8, "RES 7, E", |cpu| {
+        cpu.registers.e = cpu.res(7, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xBC, 8, "RES 7, H", |cpu| {
+        cpu.registers.h = cpu.res(7, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xBD, 8, "RES 7, L", |cpu| {
+        cpu.registers.l = cpu.res(7, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xBE, 16, "RES 7, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.res(7, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xBF, 8, "RES 7, A", |cpu| {
+        cpu.registers.a = cpu.res(7, cpu.registers.a)
+    });
+
+    // SET b, r
+    set.store_cb_instructions(0xC0, 8, "SET 0, B", |cpu| {
+        cpu.registers.b = cpu.set(0, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xC1, 8, "SET 0, C", |cpu| {
+        cpu.registers.c = cpu.set(0, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xC2, 8, "SET 0, D", |cpu| {
+        cpu.registers.d = cpu.set(0, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xC3, 8, "SET 0, E", |cpu| {
+        cpu.registers.e = cpu.set(0, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xC4, 8, "SET 0, H", |cpu| {
+        cpu.registers.h = cpu.set(0, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xC5, 8, "SET 0, L", |cpu| {
+        cpu.registers.l = cpu.set(0, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xC6, 16, "SET 0, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(0, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xC7, 8, "SET 0, A", |cpu| {
+        cpu.registers.a = cpu.set(0, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xC8, 8, "SET 1, B", |cpu| {
+        cpu.registers.b = cpu.set(1, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xC9, 8, "SET 1, C", |cpu| {
+        cpu.registers.c = cpu.set(1, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xCA, 8, "SET 1, D", |cpu| {
+        cpu.registers.d = cpu.set(1, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xCB, 8, "SET 1, E", |cpu| {
+        cpu.registers.e = cpu.set(1, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xCC, 8, "SET 1, H", |cpu| {
+        cpu.registers.h = cpu.set(1, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xCD, 8, "SET 1, L", |cpu| {
+        cpu.registers.l = cpu.set(1, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xCE, 16, "SET 1, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(1, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xCF, 8, "SET 1, A", |cpu| {
+        cpu.registers.a = cpu.set(1, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xD0, 8, "SET 2, B", |cpu| {
+        cpu.registers.b = cpu.set(2, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xD1, 8, "SET 2, C", |cpu| {
+        cpu.registers.c = cpu.set(2, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xD2, 8, "SET 2, D", |cpu| {
+        cpu.registers.d = cpu.set(2, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xD3, 8, "SET 2, E", |cpu| {
+        cpu.registers.e = cpu.set(2, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xD4, 8, "SET 2, H", |cpu| {
+        cpu.registers.h = cpu.set(2, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xD5, 8, "SET 2, L", |cpu| {
+        cpu.registers.l = cpu.set(2, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xD6, 16, "SET 2, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(2, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xD7, 8, "SET 2, A", |cpu| {
+        cpu.registers.a = cpu.set(2, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xD8, 8, "SET 3, B", |cpu| {
+        cpu.registers.b = cpu.set(3, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xD9, 8, "SET 3, C", |cpu| {
+        cpu.registers.c = cpu.set(3, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xDA, 8, "SET 3, D", |cpu| {
+        cpu.registers.d = cpu.set(3, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xDB, 8, "SET 3, E", |cpu| {
+        cpu.registers.e = cpu.set(3, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xDC, 8, "SET 3, H", |cpu| {
+        cpu.registers.h = cpu.set(3, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xDD, 8, "SET 3, L", |cpu| {
+        cpu.registers.l = cpu.set(3, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xDE, 16, "SET 3, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(3, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xDF, 8, "SET 3, A", |cpu| {
+        cpu.registers.a = cpu.set(3, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xE0, 8, "SET 4, B", |cpu| {
+        cpu.registers.b = cpu.set(4, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xE1, 8, "SET 4, C", |cpu| {
+        cpu.registers.c = cpu.set(4, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xE2, 8, "SET 4, D", |cpu| {
+        cpu.registers.d = cpu.set(4, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xE3, 8, "SET 4, E", |cpu| {
+        cpu.registers.e = cpu.set(4, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xE4, 8, "SET 4, H", |cpu| {
+        cpu.registers.h = cpu.set(4, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xE5, 8, "SET 4, L", |cpu| {
+        cpu.registers.l = cpu.set(4, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xE6, 16, "SET 4, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(4, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xE7, 8, "SET 4, A", |cpu| {
+        cpu.registers.a = cpu.set(4, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xE8, 8, "SET 5, B", |cpu| {
+        cpu.registers.b = cpu.set(5, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xE9, 8, "SET 5, C", |cpu| {
+        cpu.registers.c = cpu.set(5, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xEA, 8, "SET 5, D", |cpu| {
+        cpu.registers.d = cpu.set(5, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xEB, 8, "SET 5, E", |cpu| {
+        cpu.registers.e = cpu.set(5, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xEC, 8, "SET 5, H", |cpu| {
+        cpu.registers.h = cpu.set(5, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xED, 8, "SET 5, L", |cpu| {
+        cpu.registers.l = cpu.set(5, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xEE, 16, "SET 5, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(5, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xEF, 8, "SET 5, A", |cpu| {
+        cpu.registers.a = cpu.set(5, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xF0, 8, "SET 6, B", |cpu| {
+        cpu.registers.b = cpu.set(6, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xF1, 8, "SET 6, C", |cpu| {
+        cpu.registers.c = cpu.set(6, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xF2, 8, "SET 6, D", |cpu| {
+        cpu.registers.d = cpu.set(6, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xF3, 8, "SET 6, E", |cpu| {
+        cpu.registers.e = cpu.set(6, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xF4, 8, "SET 6, H", |cpu| {
+        cpu.registers.h = cpu.set(6, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xF5, 8, "SET 6, L", |cpu| {
+        cpu.registers.l = cpu.set(6, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xF6, 16, "SET 6, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(6, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xF7, 8, "SET 6, A", |cpu| {
+        cpu.registers.a = cpu.set(6, cpu.registers.a)
+    });
+    set.store_cb_instructions(0xF8, 8, "SET 7, B", |cpu| {
+        cpu.registers.b = cpu.set(7, cpu.registers.b)
+    });
+    set.store_cb_instructions(0xF9, 8, "SET 7, C", |cpu| {
+        cpu.registers.c = cpu.set(7, cpu.registers.c)
+    });
+    set.store_cb_instructions(0xFA, 8, "SET 7, D", |cpu| {
+        cpu.registers.d = cpu.set(7, cpu.registers.d)
+    });
+    set.store_cb_instructions(0xFB, 8, "SET 7, E", |cpu| {
+        cpu.registers.e = cpu.set(7, cpu.registers.e)
+    });
+    set.store_cb_instructions(0xFC, 8, "SET 7, H", |cpu| {
+        cpu.registers.h = cpu.set(7, cpu.registers.h)
+    });
+    set.store_cb_instructions(0xFD, 8, "SET 7, L", |cpu| {
+        cpu.registers.l = cpu.set(7, cpu.registers.l)
+    });
+    set.store_cb_instructions(0xFE, 16, "SET 7, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.set(7, value);
+        cpu.write_hl(value);
+    });
+    set.store_cb_instructions(0xFF, 8, "SET 7, A", |cpu| {
+        cpu.registers.a = cpu.set(7, cpu.registers.a)
+    });
+}
+
+impl<B: Bus> CPU<B> {
+    // rotates and shifts all set Z from the result and C from the bit shifted out
+    fn set_shift_flags(&mut self, result: u8, carry: bool) {
+        self.registers.f.set_z(result == 0);
+        self.registers.f.set_n(false);
+        self.registers.f.set_h(false);
+        self.registers.f.set_c(carry);
+    }
+
+    pub fn rlc(&mut self, value: u8) -> u8 {
+        let result = value.rotate_left(1);
+        self.set_shift_flags(result, value & 0x80 != 0);
+        result
+    }
+
+    pub fn rrc(&mut self, value: u8) -> u8 {
+        let result = value.rotate_right(1);
+        self.set_shift_flags(result, value & 0x01 != 0);
+        result
+    }
+
+    // RL and RR rotate through the carry flag
+    pub fn rl(&mut self, value: u8) -> u8 {
+        let result = (value << 1) | self.registers.f.c() as u8;
+        self.set_shift_flags(result, value & 0x80 != 0);
+        result
+    }
+
+    pub fn rr(&mut self, value: u8) -> u8 {
+        let result = (value >> 1) | ((self.registers.f.c() as u8) << 7);
+        self.set_shift_flags(result, value & 0x01 != 0);
+        result
+    }
+
+    pub fn sla(&mut self, value: u8) -> u8 {
+        let result = value << 1;
+        self.set_shift_flags(result, value & 0x80 != 0);
+        result
+    }
+
+    // arithmetic shift: bit 7 keeps its value
+    pub fn sra(&mut self, value: u8) -> u8 {
+        let result = (value >> 1) | (value & 0x80);
+        self.set_shift_flags(result, value & 0x01 != 0);
+        result
+    }
+
+    pub fn srl(&mut self, value: u8) -> u8 {
+        let result = value >> 1;
+        self.set_shift_flags(result, value & 0x01 != 0);
+        result
+    }
+
+    pub fn swap(&mut self, value: u8) -> u8 {
+        let result = value.rotate_left(4);
+        self.set_shift_flags(result, false);
+        result
+    }
+
+    // BIT leaves the carry flag untouched
+    pub fn bit(&mut self, bit: u8, value: u8) {
+        self.registers.f.set_z(value & (1 << bit) == 0);
+        self.registers.f.set_n(false);
+        self.registers.f.set_h(true);
+    }
+
+    pub fn res(&self, bit: u8, value: u8) -> u8 {
+        value & !(1 << bit)
+    }
+
+    pub fn set(&self, bit: u8, value: u8) -> u8 {
+        value | (1 << bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::test_cpu;
+
+    #[test]
+    fn register_operands_take_two_cycles_and_hl_four() {
+        // RLC B ; RLC (HL) ; BIT 0, (HL)
+        let mut cpu = test_cpu(&[0xCB, 0x00, 0xCB, 0x06, 0xCB, 0x46]);
+        cpu.registers.h = 0xC0;
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.step().unwrap(), 16);
+        // BIT only reads (HL)
+        assert_eq!(cpu.step().unwrap(), 12);
+    }
+
+    #[test]
+    fn rotates_through_the_carry() {
+        // RL A ; RR B
+        let mut cpu = test_cpu(&[0xCB, 0x17, 0xCB, 0x18]);
+        cpu.registers.a = 0x80;
+        cpu.registers.b = 0x01;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.z());
+        assert!(cpu.registers.f.c());
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x80);
+        assert!(!cpu.registers.f.z());
+        assert!(cpu.registers.f.c());
+    }
+
+    #[test]
+    fn sra_keeps_the_sign_and_srl_does_not() {
+        // SRA A ; SRL B
+        let mut cpu = test_cpu(&[0xCB, 0x2F, 0xCB, 0x38]);
+        cpu.registers.a = 0x81;
+        cpu.registers.b = 0x81;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0xC0);
+        assert!(cpu.registers.f.c());
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x40);
+        assert!(cpu.registers.f.c());
+    }
+
+    #[test]
+    fn swap_clears_the_carry() {
+        // SWAP A
+        let mut cpu = test_cpu(&[0xCB, 0x37]);
+        cpu.registers.a = 0xF1;
+        cpu.registers.f.set_c(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x1F);
+        assert_eq!(cpu.registers.f.bits(), 0x00);
+    }
+
+    #[test]
+    fn bit_sets_half_carry_and_keeps_the_carry() {
+        // BIT 7, H ; BIT 0, H
+        let mut cpu = test_cpu(&[0xCB, 0x7C, 0xCB, 0x44]);
+        cpu.registers.h = 0x80;
+        cpu.registers.f.set_c(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.f.bits(), 0x30);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.f.bits(), 0xB0);
+    }
+
+    #[test]
+    fn res_and_set_write_back_to_hl() {
+        // SET 3, (HL) ; RES 7, (HL)
+        let mut cpu = test_cpu(&[0xCB, 0xDE, 0xCB, 0xBE]);
+        cpu.registers.h = 0xC0;
+        cpu.memory.write_bytes(0xC000, 0x80);
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.read_bytes(0xC000), 0x88);
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.read_bytes(0xC000), 0x08);
+    }
+}
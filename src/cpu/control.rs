@@ -0,0 +1,147 @@
+use super::CPU;
+use crate::bus::Bus;
+
+// jumps, calls and returns
+// conditional forms always read their operands, and only report
+// the longer duration through branch_taken when the condition holds
+impl<B: Bus> CPU<B> {
+    pub fn read_next_u16(&mut self) -> u16 {
+        let first_byte = self.read_next_opcode();
+        let second_byte = self.read_next_opcode();
+        u16::from_le_bytes([first_byte, second_byte])
+    }
+
+    pub fn jp(&mut self, condition: bool) {
+        let address = self.read_next_u16();
+        if condition {
+            self.internal_cycle();
+            self.registers.pc = address;
+            self.branch_taken = true;
+        }
+    }
+
+    // the offset is signed and relative to the address following the operand
+    pub fn jr(&mut self, condition: bool) {
+        let offset = self.read_next_opcode() as i8;
+        if condition {
+            self.internal_cycle();
+            self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
+            self.branch_taken = true;
+        }
+    }
+
+    pub fn call(&mut self, condition: bool) {
+        let address = self.read_next_u16();
+        if condition {
+            self.push_u16(self.registers.pc);
+            self.registers.pc = address;
+            self.branch_taken = true;
+        }
+    }
+
+    // checking the condition takes an internal cycle, unlike JP, JR and CALL
+    pub fn ret(&mut self, condition: bool) {
+        self.internal_cycle();
+        if condition {
+            self.registers.pc = self.pop_u16();
+            self.internal_cycle();
+            self.branch_taken = true;
+        }
+    }
+
+    pub fn rst(&mut self, address: u16) {
+        self.push_u16(self.registers.pc);
+        self.registers.pc = address;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::test_cpu;
+
+    #[test]
+    fn conditional_jp_takes_an_extra_cycle_when_taken() {
+        // JP NZ, $1234 ; JP Z, $1234
+        let mut cpu = test_cpu(&[0xC2, 0x34, 0x12, 0xCA, 0x34, 0x12]);
+        cpu.registers.f.set_z(true);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.pc, 0x0003);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.registers.pc, 0x1234);
+    }
+
+    #[test]
+    fn jr_offset_is_signed_and_relative_to_the_next_instruction() {
+        // NOP ; JR NZ, -3 ; JR -5
+        let mut cpu = test_cpu(&[0x00, 0x20, 0xFD, 0x18, 0xFB]);
+        cpu.registers.f.set_z(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.registers.pc, 0x0003);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.pc, 0x0000);
+    }
+
+    #[test]
+    fn call_and_ret_go_through_the_stack() {
+        // CALL $0010 ... 0x0010: RET
+        let mut program = vec![0; 0x11];
+        program[..3].copy_from_slice(&[0xCD, 0x10, 0x00]);
+        program[0x10] = 0xC9;
+        let mut cpu = test_cpu(&program);
+
+        assert_eq!(cpu.step().unwrap(), 24);
+        assert_eq!(cpu.registers.pc, 0x0010);
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(cpu.memory.read_bytes(0xFFFC), 0x03);
+        assert_eq!(cpu.memory.read_bytes(0xFFFD), 0x00);
+
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.registers.pc, 0x0003);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn call_not_taken_reads_its_operand_without_pushing() {
+        // CALL Z, $1234
+        let mut cpu = test_cpu(&[0xCC, 0x34, 0x12]);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.pc, 0x0003);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn conditional_ret_spends_a_cycle_on_the_condition() {
+        // RET Z ; RET NZ
+        let mut cpu = test_cpu(&[0xC8, 0xC0]);
+        cpu.registers.sp = 0xFFFC;
+        cpu.memory.write_bytes(0xFFFC, 0x34);
+        cpu.memory.write_bytes(0xFFFD, 0x12);
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.registers.pc, 0x0001);
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.registers.pc, 0x1234);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn rst_pushes_the_address_of_the_next_instruction() {
+        // NOP ; RST 28H
+        let mut cpu = test_cpu(&[0x00, 0xEF]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.registers.pc, 0x0028);
+        assert_eq!(cpu.memory.read_bytes(0xFFFC), 0x02);
+        assert_eq!(cpu.memory.read_bytes(0xFFFD), 0x00);
+    }
+
+    #[test]
+    fn jp_hl_takes_a_single_cycle() {
+        // JP (HL)
+        let mut cpu = test_cpu(&[0xE9]);
+        cpu.registers.h = 0x40;
+        cpu.registers.l = 0x00;
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.registers.pc, 0x4000);
+    }
+}
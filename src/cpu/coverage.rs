@@ -0,0 +1,284 @@
+use std::fmt;
+
+use super::error::ILLEGAL_OP_CODES;
+use super::instruction::{Conflict, Instruction, InstructionSet};
+use super::opcodes::{
+    CB_OP_CODES, CB_OP_CODE_CYCLES, OP_CODES, OP_CODE_CYCLES, OP_CODE_CYCLES_TAKEN,
+};
+use crate::memory::Memory;
+
+// an implemented opcode whose mnemonic differs from the reference one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub op_code: u8,
+    pub prefixed: bool,
+    pub registered: &'static str,
+    pub expected: &'static str,
+}
+
+// an implemented opcode whose registered cycles differ from the published timings
+// `taken` is the duration of a conditional branch when its condition holds
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimingMismatch {
+    pub op_code: u8,
+    pub prefixed: bool,
+    pub registered: (u8, u8),
+    pub expected: (u8, u8),
+}
+
+// which of the 256 + 256 opcodes have a handler, compared to the reference tables
+#[derive(Clone, Debug, Default)]
+pub struct CoverageReport {
+    pub implemented: usize,
+    pub cb_implemented: usize,
+    // (op_code, prefixed)
+    pub missing: Vec<(u8, bool)>,
+    pub mismatches: Vec<Mismatch>,
+    pub timing_mismatches: Vec<TimingMismatch>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl CoverageReport {
+    pub fn new(set: &InstructionSet) -> CoverageReport {
+        let mut report = CoverageReport {
+            conflicts: set.conflicts.clone(),
+            ..CoverageReport::default()
+        };
+        report.check_table(&set.instructions, &OP_CODES, false);
+        report.check_table(&set.cb_instructions, &CB_OP_CODES, true);
+        report
+    }
+
+    fn check_table(
+        &mut self,
+        table: &[Option<Instruction>; 256],
+        reference: &[&'static str; 256],
+        prefixed: bool,
+    ) {
+        for (op_code, slot) in table.iter().enumerate() {
+            let op_code = op_code as u8;
+            // illegal opcodes and the prefix itself never get a handler
+            if !prefixed && (op_code == 0xCB || ILLEGAL_OP_CODES.contains(&op_code)) {
+                continue;
+            }
+
+            let expected = reference[op_code as usize];
+            match slot {
+                None => self.missing.push((op_code, prefixed)),
+                Some(inst) => {
+                    if prefixed {
+                        self.cb_implemented += 1;
+                    } else {
+                        self.implemented += 1;
+                    }
+                    if normalize(inst._str) != normalize(expected) {
+                        self.mismatches.push(Mismatch {
+                            op_code,
+                            prefixed,
+                            registered: inst._str,
+                            expected,
+                        });
+                    }
+                    let registered = (inst.cycles, inst.cycles_taken);
+                    let expected = expected_cycles(op_code, prefixed);
+                    if registered != expected {
+                        self.timing_mismatches.push(TimingMismatch {
+                            op_code,
+                            prefixed,
+                            registered,
+                            expected,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+            && self.mismatches.is_empty()
+            && self.timing_mismatches.is_empty()
+            && self.conflicts.is_empty()
+    }
+}
+
+// (not taken, taken) cycles of an opcode in the published tables
+fn expected_cycles(op_code: u8, prefixed: bool) -> (u8, u8) {
+    let op_code = op_code as usize;
+    if prefixed {
+        (CB_OP_CODE_CYCLES[op_code], CB_OP_CODE_CYCLES[op_code])
+    } else {
+        (OP_CODE_CYCLES[op_code], OP_CODE_CYCLES_TAKEN[op_code])
+    }
+}
+
+// mnemonics are compared without whitespace or case
+fn normalize(mnemonic: &str) -> String {
+    mnemonic
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn op_code_name(op_code: u8, prefixed: bool) -> String {
+    if prefixed {
+        format!("0xCB {:#04X}", op_code)
+    } else {
+        format!("{:#04X}", op_code)
+    }
+}
+
+// opcode followed by its reference mnemonic, e.g. "0xCB 0x46 BIT 0, (HL)"
+fn op_code_with_mnemonic(op_code: u8, prefixed: bool) -> String {
+    let reference = if prefixed { CB_OP_CODES } else { OP_CODES };
+    format!(
+        "{} {}",
+        op_code_name(op_code, prefixed),
+        reference[op_code as usize]
+    )
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let legal = 256 - 1 - ILLEGAL_OP_CODES.len();
+        writeln!(f, "implemented: {}/{}", self.implemented, legal)?;
+        writeln!(f, "cb implemented: {}/256", self.cb_implemented)?;
+
+        writeln!(f, "missing: {}", self.missing.len())?;
+        for (op_code, prefixed) in &self.missing {
+            writeln!(f, "  {}", op_code_with_mnemonic(*op_code, *prefixed))?;
+        }
+
+        writeln!(f, "mismatched mnemonics: {}", self.mismatches.len())?;
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "  {} registered as \"{}\", expected \"{}\"",
+                op_code_name(mismatch.op_code, mismatch.prefixed),
+                mismatch.registered,
+                mismatch.expected
+            )?;
+        }
+
+        writeln!(f, "mismatched timings: {}", self.timing_mismatches.len())?;
+        for mismatch in &self.timing_mismatches {
+            writeln!(
+                f,
+                "  {} registered with {}/{} cycles, expected {}/{}",
+                op_code_with_mnemonic(mismatch.op_code, mismatch.prefixed),
+                mismatch.registered.0,
+                mismatch.registered.1,
+                mismatch.expected.0,
+                mismatch.expected.1
+            )?;
+        }
+
+        writeln!(f, "conflicts: {}", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            writeln!(
+                f,
+                "  {} registered as \"{}\", \"{}\" rejected",
+                op_code_with_mnemonic(conflict.op_code, conflict.prefixed),
+                conflict.kept,
+                conflict.rejected
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// coverage of the opcode tables used by every cpu
+pub fn coverage_report() -> CoverageReport {
+    CoverageReport::new(&super::instruction_set::<Memory>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nop(_: &mut crate::cpu::CPU) {}
+
+    #[test]
+    fn instruction_set_matches_the_reference_tables() {
+        let report = coverage_report();
+        assert!(report.missing.is_empty(), "{}", report);
+        assert!(report.mismatches.is_empty(), "{}", report);
+        assert!(report.conflicts.is_empty(), "{}", report);
+        assert!(report.is_complete());
+    }
+
+    #[test]
+    fn registered_timings_match_the_published_tables() {
+        let report = coverage_report();
+        assert!(report.timing_mismatches.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn registering_an_opcode_twice_keeps_the_first_handler() {
+        let mut set = InstructionSet::new();
+        set.store_instructions(0x00, 4, "NOP", nop);
+        set.store_instructions(0x00, 8, "LD B,B", nop);
+        assert_eq!(set.instructions[0x00].unwrap()._str, "NOP");
+        assert_eq!(
+            set.conflicts,
+            vec![Conflict {
+                op_code: 0x00,
+                prefixed: false,
+                kept: "NOP",
+                rejected: "LD B,B",
+            }]
+        );
+        assert!(!CoverageReport::new(&set).is_complete());
+    }
+
+    #[test]
+    fn wrong_timings_are_reported() {
+        let mut set = InstructionSet::new();
+        set.store_branch_instructions(0x20, 8, 8, "JR NZ,n", nop);
+        set.store_cb_instructions(0x46, 16, "BIT 0,(HL)", nop);
+        let report = CoverageReport::new(&set);
+        assert_eq!(
+            report.timing_mismatches,
+            vec![
+                TimingMismatch {
+                    op_code: 0x20,
+                    prefixed: false,
+                    registered: (8, 8),
+                    expected: (8, 12),
+                },
+                TimingMismatch {
+                    op_code: 0x46,
+                    prefixed: true,
+                    registered: (16, 16),
+                    expected: (12, 12),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_names_the_opcodes_it_lists() {
+        let mut set = InstructionSet::new();
+        set.store_branch_instructions(0x20, 8, 8, "JR NZ,n", nop);
+        let output = CoverageReport::new(&set).to_string();
+        assert!(
+            output.contains(&format!("0x00 {}", OP_CODES[0x00])),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(&format!("0xCB 0x7C {}", CB_OP_CODES[0x7C])),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains(&format!(
+                "0x20 {} registered with 8/8 cycles",
+                OP_CODES[0x20]
+            )),
+            "{}",
+            output
+        );
+    }
+}
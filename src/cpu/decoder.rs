@@ -0,0 +1,344 @@
+use std::fmt;
+
+use super::opcodes::{
+    CB_OP_CODES, CB_OP_CODE_CYCLES, OP_CODES, OP_CODE_CYCLES, OP_CODE_CYCLES_TAKEN,
+};
+
+// immediate value following the opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    U8(u8),
+    U16(u16),
+    // relative jumps and the SP offsets of ADD SP, n and LDHL SP, n
+    I8(i8),
+}
+
+// how an instruction changes one flag, following the GB CPU Manual notation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagEffect {
+    Unaffected,
+    Reset,
+    Set,
+    Affected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlagEffects {
+    pub z: FlagEffect,
+    pub n: FlagEffect,
+    pub h: FlagEffect,
+    pub c: FlagEffect,
+}
+
+impl FlagEffects {
+    // parses the "ZNHC" notation: '-' unaffected, '0' reset, '1' set, anything else affected
+    fn parse(notation: &str) -> FlagEffects {
+        let effects: Vec<FlagEffect> = notation
+            .chars()
+            .map(|flag| match flag {
+                '-' => FlagEffect::Unaffected,
+                '0' => FlagEffect::Reset,
+                '1' => FlagEffect::Set,
+                _ => FlagEffect::Affected,
+            })
+            .collect();
+        FlagEffects {
+            z: effects[0],
+            n: effects[1],
+            h: effects[2],
+            c: effects[3],
+        }
+    }
+}
+
+impl fmt::Display for FlagEffects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (effect, name) in [(self.z, 'Z'), (self.n, 'N'), (self.h, 'H'), (self.c, 'C')] {
+            let symbol = match effect {
+                FlagEffect::Unaffected => '-',
+                FlagEffect::Reset => '0',
+                FlagEffect::Set => '1',
+                FlagEffect::Affected => name,
+            };
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+// an instruction decoded from memory without executing it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub address: u16,
+    pub op_code: u8,
+    pub prefixed: bool,
+    // reference mnemonic with its operand placeholder, empty for illegal opcodes
+    pub mnemonic: &'static str,
+    pub operand: Option<Operand>,
+    pub length: u8,
+    // `cycles_taken` differs from `cycles` for conditional branches only
+    pub cycles: u8,
+    pub cycles_taken: u8,
+    pub flags: FlagEffects,
+}
+
+impl DecodedInstruction {
+    pub fn is_illegal(&self) -> bool {
+        self.mnemonic.is_empty()
+    }
+
+    // destination of JR, JP nn and CALL nn
+    pub fn target(&self) -> Option<u16> {
+        match (self.mnemonic.split(' ').next(), self.operand) {
+            (Some("JR"), Some(Operand::I8(offset))) => Some(
+                self.address
+                    .wrapping_add(self.length as u16)
+                    .wrapping_add(offset as u16),
+            ),
+            (Some("JP" | "CALL"), Some(Operand::U16(address))) => Some(address),
+            _ => None,
+        }
+    }
+
+    fn format_operand(&self, placeholder: &str) -> String {
+        let value = match (self.operand, self.target()) {
+            (_, Some(target)) => format!("${:04X}", target),
+            (Some(Operand::U8(value)), _) if self.mnemonic.starts_with("LDH") => {
+                format!("${:04X}", 0xFF00 | value as u16)
+            }
+            (Some(Operand::U8(value)), _) => format!("${:02X}", value),
+            (Some(Operand::U16(value)), _) => format!("${:04X}", value),
+            (Some(Operand::I8(value)), _) => format!("{:+}", value),
+            (None, _) => return placeholder.to_string(),
+        };
+        if placeholder.starts_with('(') {
+            format!("({})", value)
+        } else {
+            value
+        }
+    }
+}
+
+// prints the instruction with its operand, e.g. "LD B, $12" or "JR NZ, $0150"
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_illegal() {
+            return write!(f, "DB ${:02X}", self.op_code);
+        }
+
+        let (name, operands) = match self.mnemonic.split_once(' ') {
+            Some((name, operands)) => (name, operands),
+            None => return write!(f, "{}", self.mnemonic),
+        };
+        let operands: Vec<String> = operands
+            .split(", ")
+            .map(|operand| match operand {
+                "n" | "(n)" | "nn" | "(nn)" => self.format_operand(operand),
+                _ => operand.to_string(),
+            })
+            .collect();
+        write!(f, "{} {}", name, operands.join(", "))
+    }
+}
+
+// decodes the instruction at the start of `bytes`, located at `address` in memory
+// missing bytes at the end of the slice read as 0xFF, like unmapped memory
+pub fn decode(bytes: &[u8], address: u16) -> DecodedInstruction {
+    let byte = |index: usize| bytes.get(index).copied().unwrap_or(0xFF);
+    let first = byte(0);
+    let prefixed = first == 0xCB;
+    let op_code = if prefixed { byte(1) } else { first };
+    // plain table lookups, illegal opcodes have no mnemonic and 0 cycles
+    let index = op_code as usize;
+    let (mnemonic, cycles, cycles_taken) = if prefixed {
+        let cycles = CB_OP_CODE_CYCLES[index];
+        (CB_OP_CODES[index], cycles, cycles)
+    } else {
+        (
+            OP_CODES[index],
+            OP_CODE_CYCLES[index],
+            OP_CODE_CYCLES_TAKEN[index],
+        )
+    };
+
+    let operand_length = if prefixed {
+        1
+    } else {
+        operand_length(mnemonic)
+    };
+    let operand = match (prefixed, operand_length) {
+        (false, 1) if is_signed_operand(mnemonic) => Some(Operand::I8(byte(1) as i8)),
+        (false, 1) => Some(Operand::U8(byte(1))),
+        (false, 2) => Some(Operand::U16(u16::from_le_bytes([byte(1), byte(2)]))),
+        _ => None,
+    };
+    // STOP is followed by a padding byte
+    let length = if op_code == 0x10 && !prefixed {
+        2
+    } else {
+        1 + operand_length
+    };
+
+    DecodedInstruction {
+        address,
+        op_code,
+        prefixed,
+        mnemonic,
+        operand,
+        length,
+        cycles,
+        cycles_taken,
+        flags: FlagEffects::parse(flags_notation(mnemonic, prefixed)),
+    }
+}
+
+// bytes of immediate data following the opcode
+fn operand_length(mnemonic: &str) -> u8 {
+    let operands = match mnemonic.split_once(' ') {
+        Some((_, operands)) => operands,
+        None => return 0,
+    };
+    operands
+        .split(", ")
+        .map(|operand| match operand {
+            "n" | "(n)" => 1,
+            "nn" | "(nn)" => 2,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn is_signed_operand(mnemonic: &str) -> bool {
+    mnemonic.starts_with("JR") || mnemonic.ends_with("SP, n")
+}
+
+fn flags_notation(mnemonic: &str, prefixed: bool) -> &'static str {
+    let (name, operands) = mnemonic.split_once(' ').unwrap_or((mnemonic, ""));
+    let is_pair = matches!(operands, "BC" | "DE" | "HL" | "SP");
+
+    if prefixed {
+        return match name {
+            "SWAP" => "Z000",
+            "BIT" => "Z01-",
+            "RES" | "SET" => "----",
+            _ => "Z00C",
+        };
+    }
+
+    match name {
+        "ADD" if operands.starts_with("HL") => "-0HC",
+        "ADD" if operands.starts_with("SP") => "00HC",
+        "LDHL" => "00HC",
+        "ADD" | "ADC" => "Z0HC",
+        "SUB" | "SBC" | "CP" => "Z1HC",
+        "AND" => "Z010",
+        "OR" | "XOR" => "Z000",
+        "INC" | "DEC" if is_pair => "----",
+        "INC" => "Z0H-",
+        "DEC" => "Z1H-",
+        "DAA" => "Z-0C",
+        "CPL" => "-11-",
+        "SCF" => "-001",
+        "CCF" => "-00C",
+        "RLCA" | "RRCA" | "RLA" | "RRA" => "000C",
+        "POP" if operands == "AF" => "ZNHC",
+        _ => "----",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    #[test]
+    fn operands_are_read_little_endian() {
+        let inst = decode(&[0x06, 0x12], 0x0100);
+        assert_eq!(inst.operand, Some(Operand::U8(0x12)));
+        assert_eq!(inst.length, 2);
+        assert_eq!(inst.to_string(), "LD B, $12");
+
+        let inst = decode(&[0xFA, 0x34, 0x12], 0x0100);
+        assert_eq!(inst.operand, Some(Operand::U16(0x1234)));
+        assert_eq!(inst.length, 3);
+        assert_eq!(inst.cycles, 16);
+        assert_eq!(inst.to_string(), "LD A, ($1234)");
+    }
+
+    #[test]
+    fn relative_jumps_show_their_target() {
+        // JR NZ, -2 loops on itself
+        let inst = decode(&[0x20, 0xFE], 0x0150);
+        assert_eq!(inst.operand, Some(Operand::I8(-2)));
+        assert_eq!(inst.target(), Some(0x0150));
+        assert_eq!((inst.cycles, inst.cycles_taken), (8, 12));
+        assert_eq!(inst.to_string(), "JR NZ, $0150");
+
+        let inst = decode(&[0xCD, 0x00, 0x40], 0x0000);
+        assert_eq!(inst.target(), Some(0x4000));
+        assert_eq!((inst.cycles, inst.cycles_taken), (24, 24));
+    }
+
+    #[test]
+    fn high_page_and_sp_offsets_are_formatted() {
+        assert_eq!(decode(&[0xE0, 0x44], 0).to_string(), "LDH ($FF44), A");
+        assert_eq!(decode(&[0xF8, 0xF0], 0).to_string(), "LDHL SP, -16");
+        assert_eq!(decode(&[0xE8, 0x05], 0).to_string(), "ADD SP, +5");
+    }
+
+    #[test]
+    fn prefixed_instructions_are_two_bytes() {
+        let inst = decode(&[0xCB, 0x7C], 0);
+        assert!(inst.prefixed);
+        assert_eq!(inst.op_code, 0x7C);
+        assert_eq!(inst.length, 2);
+        assert_eq!(inst.cycles, 8);
+        assert_eq!(inst.flags.to_string(), "Z01-");
+        assert_eq!(inst.to_string(), "BIT 7, H");
+    }
+
+    #[test]
+    fn stop_skips_its_padding_byte() {
+        let inst = decode(&[0x10, 0x00], 0);
+        assert_eq!(inst.length, 2);
+        assert_eq!(inst.operand, None);
+    }
+
+    #[test]
+    fn illegal_opcodes_are_shown_as_data() {
+        let inst = decode(&[0xD3], 0);
+        assert!(inst.is_illegal());
+        assert_eq!(inst.length, 1);
+        assert_eq!(inst.cycles, 0);
+        assert_eq!(inst.to_string(), "DB $D3");
+    }
+
+    #[test]
+    fn missing_bytes_read_as_ff() {
+        let inst = decode(&[0xC3], 0);
+        assert_eq!(inst.operand, Some(Operand::U16(0xFFFF)));
+    }
+
+    #[test]
+    fn flag_effects_follow_the_instruction() {
+        let flags = |bytes: &[u8]| decode(bytes, 0).flags.to_string();
+        assert_eq!(flags(&[0x80]), "Z0HC");
+        assert_eq!(flags(&[0x09]), "-0HC");
+        assert_eq!(flags(&[0x03]), "----");
+        assert_eq!(flags(&[0x3C]), "Z0H-");
+        assert_eq!(flags(&[0xF1]), "ZNHC");
+        assert_eq!(flags(&[0xC1]), "----");
+        assert_eq!(flags(&[0x17]), "000C");
+        assert_eq!(flags(&[0xCB, 0x37]), "Z000");
+    }
+
+    #[test]
+    fn decoding_has_no_side_effect_on_the_machine() {
+        // LD A, n
+        let cpu = test_cpu(&[0x3E, 0x42]);
+        let inst = cpu.decode_at(0x0000);
+        assert_eq!(inst.to_string(), "LD A, $42");
+        assert_eq!(cpu.cycles(), 0);
+        assert_eq!(cpu.registers.pc, 0x0000);
+    }
+}
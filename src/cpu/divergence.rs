@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use super::{decode, CpuError, Tracer, CPU, CYCLES_PER_FRAME};
+
+// gives up when the cpu runs this long without executing an instruction (HALT with no interrupt)
+const STALL_CYCLES: u64 = 60 * CYCLES_PER_FRAME;
+
+// first line where a run differs from a reference Gameboy Doctor trace
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    // 1 based, as shown by text editors
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+    // names of the fields that differ, e.g. ["A", "F"]
+    pub fields: Vec<String>,
+    // matching lines right before the divergence
+    pub before: Vec<String>,
+    // lines following the divergence on each side
+    pub expected_after: Vec<String>,
+    pub actual_after: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum DivergenceError {
+    Io(io::Error),
+    // the cpu failed before the end of the reference
+    Cpu { line: usize, error: CpuError },
+    Stalled { line: usize },
+}
+
+impl fmt::Display for DivergenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DivergenceError::Io(error) => {
+                write!(f, "could not read the reference trace: {}", error)
+            }
+            DivergenceError::Cpu { line, error } => {
+                write!(f, "{} before reference line {}", error, line)
+            }
+            DivergenceError::Stalled { line } => write!(
+                f,
+                "no instruction executed for {} cycles before reference line {}",
+                STALL_CYCLES, line
+            ),
+        }
+    }
+}
+
+impl Error for DivergenceError {}
+
+impl From<io::Error> for DivergenceError {
+    fn from(error: io::Error) -> Self {
+        DivergenceError::Io(error)
+    }
+}
+
+// runs the cpu while reading the reference trace line by line, stops at the first
+// difference and keeps `context` lines around it
+// replaces the tracer of the cpu and stubs LY, returns None when the whole reference matched
+pub fn find_divergence<R: BufRead>(
+    cpu: &mut CPU,
+    reference: R,
+    context: usize,
+) -> Result<Option<Divergence>, DivergenceError> {
+    let tracer = Tracer::in_memory();
+    cpu.tracer = Some(tracer.clone());
+    // reference logs are made with LY stuck at 0x90
+    cpu.memory.doctor = true;
+
+    let mut reference = reference.lines();
+    let mut before = VecDeque::with_capacity(context + 1);
+    let mut pending = VecDeque::new();
+    // a cpu failure is only raised once the lines traced before it have been compared
+    let mut failure = None;
+    let mut line = 0;
+    while let Some(expected) = reference.next() {
+        let expected = expected?;
+        line += 1;
+
+        let actual = match pending.pop_front() {
+            Some(actual) => actual,
+            None => {
+                if let Some(error) = failure.take() {
+                    return Err(error);
+                }
+                let (lines, error) = next_lines(cpu, &tracer, line);
+                pending.extend(lines);
+                failure = error;
+                match pending.pop_front() {
+                    Some(actual) => actual,
+                    None => return Err(failure.take().unwrap()),
+                }
+            }
+        };
+
+        let fields = differing_fields(&expected, &actual);
+        if fields.is_empty() {
+            before.push_back(actual);
+            if before.len() > context {
+                before.pop_front();
+            }
+            continue;
+        }
+
+        let expected_after = reference.take(context).collect::<Result<Vec<_>, _>>()?;
+        let mut actual_after: Vec<String> = pending.into_iter().collect();
+        // the context after the divergence is best effort, the cpu may well fail there
+        while failure.is_none() && actual_after.len() < context {
+            let (lines, error) = next_lines(cpu, &tracer, line);
+            actual_after.extend(lines);
+            failure = error;
+        }
+        actual_after.truncate(context);
+
+        return Ok(Some(Divergence {
+            line,
+            expected,
+            actual,
+            fields,
+            before: before.into(),
+            expected_after,
+            actual_after,
+        }));
+    }
+    Ok(None)
+}
+
+// steps until at least one instruction was traced, on failure also returns the lines
+// traced before it, the error then points at the reference line after them
+fn next_lines(
+    cpu: &mut CPU,
+    tracer: &Tracer,
+    line: usize,
+) -> (Vec<String>, Option<DivergenceError>) {
+    let start = cpu.cycles();
+    loop {
+        if let Err(error) = cpu.step() {
+            let lines = tracer.take_lines();
+            let line = line + lines.len();
+            return (lines, Some(DivergenceError::Cpu { line, error }));
+        }
+        let lines = tracer.take_lines();
+        if !lines.is_empty() {
+            return (lines, None);
+        }
+        if cpu.cycles() - start > STALL_CYCLES {
+            return (lines, Some(DivergenceError::Stalled { line }));
+        }
+    }
+}
+
+// "A:00 F:B0 ..." -> [("A", "00"), ("F", "B0"), ...]
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    line.split_whitespace()
+        .filter_map(|field| field.split_once(':'))
+        .collect()
+}
+
+fn differing_fields(expected: &str, actual: &str) -> Vec<String> {
+    let expected_fields = fields(expected);
+    // a reference line that does not parse is compared as a whole
+    if expected_fields.is_empty() {
+        if expected.trim() == actual.trim() {
+            return Vec::new();
+        }
+        return vec![String::from("line")];
+    }
+
+    let actual_fields = fields(actual);
+    expected_fields
+        .into_iter()
+        .filter(|(name, value)| {
+            !actual_fields.iter().any(|(actual_name, actual_value)| {
+                actual_name == name && actual_value.eq_ignore_ascii_case(value)
+            })
+        })
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+// disassembles the instruction of a trace line from its PC and PCMEM fields
+pub fn disassemble_line(line: &str) -> Option<String> {
+    let fields = fields(line);
+    let value = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| *value)
+    };
+
+    let pc = u16::from_str_radix(value("PC")?, 16).ok()?;
+    let bytes = value("PCMEM")?
+        .split(',')
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(decode(&bytes, pc).to_string())
+}
+
+fn write_line(f: &mut fmt::Formatter, marker: char, number: usize, line: &str) -> fmt::Result {
+    let disassembly = disassemble_line(line).unwrap_or_default();
+    writeln!(f, "{} {:>8}  {}  {}", marker, number, line, disassembly)
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "divergence at line {}, differing: {}",
+            self.line,
+            self.fields.join(", ")
+        )?;
+
+        let first = self.line - self.before.len();
+        for (index, line) in self.before.iter().enumerate() {
+            write_line(f, ' ', first + index, line)?;
+        }
+        writeln!(f, "expected:")?;
+        write_line(f, '>', self.line, &self.expected)?;
+        for (index, line) in self.expected_after.iter().enumerate() {
+            write_line(f, ' ', self.line + 1 + index, line)?;
+        }
+        writeln!(f, "actual:")?;
+        write_line(f, '>', self.line, &self.actual)?;
+        for (index, line) in self.actual_after.iter().enumerate() {
+            write_line(f, ' ', self.line + 1 + index, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    // LD A, $05 ; INC A ; LDH A, ($44) ; NOP
+    const PROGRAM: [u8; 6] = [0x3E, 0x05, 0x3C, 0xF0, 0x44, 0x00];
+
+    const REFERENCE: [&str; 4] = [
+        "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0000 PCMEM:3E,05,3C,F0",
+        "A:05 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0002 PCMEM:3C,F0,44,00",
+        "A:06 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0003 PCMEM:F0,44,00,00",
+        "A:90 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0005 PCMEM:00,00,00,00",
+    ];
+
+    #[test]
+    fn a_matching_trace_has_no_divergence() {
+        let mut cpu = test_cpu(&PROGRAM);
+        let reference = REFERENCE.join("\n");
+        // the last line only matches with LY stubbed
+        let divergence = find_divergence(&mut cpu, reference.as_bytes(), 2).unwrap();
+        assert!(divergence.is_none());
+    }
+
+    #[test]
+    fn the_first_differing_line_and_fields_are_reported() {
+        let mut cpu = test_cpu(&PROGRAM);
+        let mut reference = REFERENCE.map(String::from);
+        reference[2] = reference[2].replace("A:06 F:00", "A:07 F:20");
+        let reference = reference.join("\n");
+
+        let divergence = find_divergence(&mut cpu, reference.as_bytes(), 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.fields, ["A", "F"]);
+        assert_eq!(divergence.expected, reference.lines().nth(2).unwrap());
+        assert_eq!(divergence.actual, REFERENCE[2]);
+        assert_eq!(divergence.before, [REFERENCE[1]]);
+        assert_eq!(divergence.expected_after, [REFERENCE[3]]);
+        assert_eq!(divergence.actual_after, [REFERENCE[3]]);
+
+        let report = divergence.to_string();
+        assert!(report.starts_with("divergence at line 3, differing: A, F\n"));
+        assert!(report.contains("INC A"));
+    }
+
+    #[test]
+    fn cpu_errors_report_the_reference_line() {
+        // illegal opcode right after the first instruction, its line is traced before
+        // the cpu fails on it
+        let program = [0x00, 0xD3];
+        let reference = [
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0000 PCMEM:00,D3,00,00",
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0001 PCMEM:D3,00,00,00",
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0002 PCMEM:00,00,00,00",
+        ];
+
+        let mut cpu = test_cpu(&program);
+        match find_divergence(&mut cpu, reference.join("\n").as_bytes(), 1) {
+            Err(DivergenceError::Cpu { line: 3, .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // the traced line is still compared before the failure is reported
+        let mut cpu = test_cpu(&program);
+        let mut reference = reference.map(String::from);
+        reference[1] = reference[1].replace("A:00", "A:01");
+        let divergence = find_divergence(&mut cpu, reference.join("\n").as_bytes(), 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.line, 2);
+        assert_eq!(divergence.fields, ["A"]);
+        assert!(divergence.actual_after.is_empty());
+
+        // a reference ending with the failing instruction fully matches
+        let mut cpu = test_cpu(&program);
+        let reference = reference[..2].join("\n").replace("A:01", "A:00");
+        assert!(find_divergence(&mut cpu, reference.as_bytes(), 1)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn trace_lines_are_disassembled() {
+        assert_eq!(disassemble_line(REFERENCE[0]).as_deref(), Some("LD A, $05"));
+        assert_eq!(disassemble_line("not a trace line"), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fmt;
+
+// opcodes that do not exist on the SM83, real hardware locks up when executing them
+pub const ILLEGAL_OP_CODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    // the opcode exists but has no handler registered yet
+    UnknownOpcode {
+        op_code: u8,
+        prefixed: bool,
+        pc: u16,
+        bank: u16,
+    },
+    // the opcode does not exist on the SM83
+    IllegalOpcode {
+        op_code: u8,
+        pc: u16,
+        bank: u16,
+    },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode {
+                op_code,
+                prefixed,
+                pc,
+                bank,
+            } => {
+                let prefix = if *prefixed { "0xCB " } else { "" };
+                write!(
+                    f,
+                    "unknown opcode {}{:#04X} at {:02X}:{:04X}",
+                    prefix, op_code, bank, pc
+                )
+            }
+            CpuError::IllegalOpcode { op_code, pc, bank } => write!(
+                f,
+                "illegal opcode {:#04X} at {:02X}:{:04X}",
+                op_code, bank, pc
+            ),
+        }
+    }
+}
+
+impl Error for CpuError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::CpuError;
+    use crate::cpu::{test_cpu, InstructionSet};
+
+    #[test]
+    fn illegal_opcode_is_reported_with_its_address() {
+        // NOP ; illegal 0xD3
+        let mut cpu = test_cpu(&[0x00, 0xD3]);
+        cpu.step().unwrap();
+        let error = cpu.step().unwrap_err();
+        assert_eq!(
+            error,
+            CpuError::IllegalOpcode {
+                op_code: 0xD3,
+                pc: 0x0001,
+                bank: 0
+            }
+        );
+        assert_eq!(error.to_string(), "illegal opcode 0xD3 at 00:0001");
+    }
+
+    #[test]
+    fn illegal_opcode_can_lock_the_cpu_instead() {
+        let mut cpu = test_cpu(&[0xFD, 0x3C]);
+        cpu.lock_on_illegal = true;
+        cpu.step().unwrap();
+        assert!(cpu.locked);
+        cpu.run_for_cycles(100).unwrap();
+        assert_eq!(cpu.registers.pc, 0x0001);
+        assert_eq!(cpu.registers.a, 0);
+    }
+
+    #[test]
+    fn missing_handler_is_an_unknown_opcode() {
+        let mut cpu = test_cpu(&[0xCB, 0x37]);
+        cpu.instruction_set = Arc::new(InstructionSet::new());
+        let error = cpu.step().unwrap_err();
+        assert_eq!(
+            error,
+            CpuError::UnknownOpcode {
+                op_code: 0x37,
+                prefixed: true,
+                pc: 0x0000,
+                bank: 0
+            }
+        );
+        assert_eq!(error.to_string(), "unknown opcode 0xCB 0x37 at 00:0000");
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+
+use super::{decode, Registers};
+
+// state of the cpu right before an instruction was executed
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub pc: u16,
+    pub bank: u16,
+    // enough bytes for the longest instruction
+    pub bytes: [u8; 3],
+    pub registers: Registers,
+}
+
+// the last executed instructions, kept in a fixed size ring buffer
+#[derive(Clone)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    capacity: usize,
+    // slot overwritten by the next entry once the buffer is full
+    next: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    // oldest entry first
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        // until the buffer is full the entries are already in order
+        let split = if self.entries.len() < self.capacity {
+            0
+        } else {
+            self.next
+        };
+        let (newest, oldest) = self.entries.split_at(split);
+        oldest.iter().chain(newest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.next = 0;
+    }
+}
+
+// one instruction per line, e.g.
+// 00:0150  C3 13 02  JP $0213      A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in self.entries() {
+            let instruction = decode(&entry.bytes, entry.pc);
+            let bytes: Vec<String> = entry.bytes[..instruction.length as usize]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            let registers = &entry.registers;
+            writeln!(
+                f,
+                "{:02X}:{:04X}  {:<8}  {:<18} A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X}",
+                entry.bank,
+                entry.pc,
+                bytes.join(" "),
+                instruction.to_string(),
+                registers.a,
+                registers.f.bits(),
+                registers.b,
+                registers.c,
+                registers.d,
+                registers.e,
+                registers.h,
+                registers.l,
+                registers.sp
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::test_cpu;
+
+    fn entry(pc: u16) -> HistoryEntry {
+        HistoryEntry {
+            pc,
+            bank: 0,
+            bytes: [0x00; 3],
+            registers: Registers::new(),
+        }
+    }
+
+    fn pcs(history: &History) -> Vec<u16> {
+        history.entries().map(|entry| entry.pc).collect()
+    }
+
+    #[test]
+    fn entries_are_listed_oldest_first() {
+        let mut history = History::new(3);
+        history.record(entry(1));
+        history.record(entry(2));
+        assert_eq!(pcs(&history), [1, 2]);
+    }
+
+    #[test]
+    fn the_oldest_entries_are_overwritten_once_full() {
+        let mut history = History::new(3);
+        for pc in 1..=5 {
+            history.record(entry(pc));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(pcs(&history), [3, 4, 5]);
+
+        history.clear();
+        assert!(history.is_empty());
+        history.record(entry(6));
+        assert_eq!(pcs(&history), [6]);
+    }
+
+    #[test]
+    fn an_empty_history_keeps_nothing() {
+        let mut history = History::new(0);
+        history.record(entry(1));
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn the_cpu_records_the_instructions_it_runs() {
+        // LD B, $12 ; JP $0000
+        let mut cpu = test_cpu(&[0x06, 0x12, 0xC3, 0x00, 0x00]);
+        cpu.history = Some(History::new(2));
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+
+        let history = cpu.history.unwrap();
+        assert_eq!(pcs(&history), [0x0002, 0x0000]);
+        let lines: Vec<String> = history.to_string().lines().map(String::from).collect();
+        assert!(lines[0].starts_with("00:0002  C3 00 00  JP $0000"));
+        assert!(lines[1].starts_with("00:0000  06 12     LD B, $12"));
+        assert!(lines[1].ends_with("B:12 C:00 D:00 E:00 H:00 L:00 SP:FFFE"));
+    }
+}
@@ -1,23 +1,158 @@
+use crate::bus::Bus;
 use crate::cpu::CPU;
+use crate::memory::Memory;
 
 // pub type ExecuteFn = dyn Fn(Instruction, CPU, Vec<String>) -> i32;
 
-#[derive(Clone)]
-pub struct Instruction {
+pub struct Instruction<B: Bus = Memory> {
     pub op_code: u8,
     pub cycles: u8,
-    pub _str: String,
-    pub execute: fn(&mut CPU),
+    // cycles used instead of `cycles` when a conditional branch is taken
+    pub cycles_taken: u8,
+    pub _str: &'static str,
+    pub execute: fn(&mut CPU<B>),
 }
 
-impl Instruction {
-    pub fn new(op_code: u8, cycles: u8, _str: &str, execute: fn(&mut CPU)) -> Instruction {
+// derived Clone and Copy would require the bus itself to be Copy
+impl<B: Bus> Clone for Instruction<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B: Bus> Copy for Instruction<B> {}
+
+impl<B: Bus> Instruction<B> {
+    pub fn new(
+        op_code: u8,
+        cycles: u8,
+        _str: &'static str,
+        execute: fn(&mut CPU<B>),
+    ) -> Instruction<B> {
+        Instruction::new_branch(op_code, cycles, cycles, _str, execute)
+    }
+
+    pub fn new_branch(
+        op_code: u8,
+        cycles: u8,
+        cycles_taken: u8,
+        _str: &'static str,
+        execute: fn(&mut CPU<B>),
+    ) -> Instruction<B> {
         Instruction {
-            op_code: op_code,
-            cycles: cycles,
-            _str: _str.to_string(),
-            execute: execute,
+            op_code,
+            cycles,
+            cycles_taken,
+            _str,
+            execute,
+        }
+    }
+}
+
+// an opcode registered twice, the first registration is kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub op_code: u8,
+    pub prefixed: bool,
+    pub kept: &'static str,
+    pub rejected: &'static str,
+}
+
+// fixed size opcode tables, one for the base instructions and one for the 0xCB prefix
+// empty slots are opcodes without a handler
+pub struct InstructionSet<B: Bus = Memory> {
+    pub instructions: [Option<Instruction<B>>; 256],
+    pub cb_instructions: [Option<Instruction<B>>; 256],
+    pub conflicts: Vec<Conflict>,
+}
+
+impl<B: Bus> InstructionSet<B> {
+    pub fn new() -> InstructionSet<B> {
+        InstructionSet {
+            instructions: [None; 256],
+            cb_instructions: [None; 256],
+            conflicts: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, prefixed: bool, instruction: Instruction<B>) {
+        let table = if prefixed {
+            &mut self.cb_instructions
+        } else {
+            &mut self.instructions
+        };
+        let slot = &mut table[instruction.op_code as usize];
+        match slot {
+            Some(existing) => self.conflicts.push(Conflict {
+                op_code: instruction.op_code,
+                prefixed,
+                kept: existing._str,
+                rejected: instruction._str,
+            }),
+            None => *slot = Some(instruction),
         }
     }
+
+    pub fn store_instructions(
+        &mut self,
+        op_code: u8,
+        cycles: u8,
+        _str: &'static str,
+        execute: fn(&mut CPU<B>),
+    ) {
+        self.insert(false, Instruction::new(op_code, cycles, _str, execute));
+    }
+
+    // same as store_instructions for instructions whose duration depends on a condition:
+    // `cycles` is used when the condition fails, `cycles_taken` when the handler sets branch_taken
+    pub fn store_branch_instructions(
+        &mut self,
+        op_code: u8,
+        cycles: u8,
+        cycles_taken: u8,
+        _str: &'static str,
+        execute: fn(&mut CPU<B>),
+    ) {
+        let instruction = Instruction::new_branch(op_code, cycles, cycles_taken, _str, execute);
+        self.insert(false, instruction);
+    }
+
+    pub fn store_cb_instructions(
+        &mut self,
+        op_code: u8,
+        cycles: u8,
+        _str: &'static str,
+        execute: fn(&mut CPU<B>),
+    ) {
+        self.insert(true, Instruction::new(op_code, cycles, _str, execute));
+    }
 }
 
+impl<B: Bus> Default for InstructionSet<B> {
+    fn default() -> Self {
+        InstructionSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{instruction_set, ILLEGAL_OP_CODES};
+    use crate::memory::Memory;
+
+    #[test]
+    fn every_legal_opcode_has_a_handler_in_its_slot() {
+        let set = instruction_set::<Memory>();
+        for op_code in 0..=255u8 {
+            let inst = set.cb_instructions[op_code as usize].unwrap();
+            assert_eq!(inst.op_code, op_code);
+
+            let slot = set.instructions[op_code as usize];
+            if op_code == 0xCB || ILLEGAL_OP_CODES.contains(&op_code) {
+                assert!(slot.is_none(), "{:#04X} has a handler", op_code);
+            } else {
+                let inst = slot.unwrap_or_else(|| panic!("{:#04X} has no handler", op_code));
+                assert_eq!(inst.op_code, op_code);
+            }
+        }
+    }
+}
@@ -1,479 +1,1524 @@
+mod alu;
+mod cb;
+mod control;
+pub mod coverage;
+mod decoder;
+pub mod divergence;
+mod error;
+mod history;
 mod instruction;
+pub mod opcodes;
 mod registers;
+mod trace;
 
-use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
-use self::instruction::Instruction;
-// use self::registers::Registers;
-use crate::memory::Memory;
+pub use self::decoder::{decode, DecodedInstruction, FlagEffect, FlagEffects, Operand};
+pub use self::error::{CpuError, ILLEGAL_OP_CODES};
+pub use self::history::{History, HistoryEntry};
+pub use self::instruction::{Conflict, Instruction, InstructionSet};
+pub use self::registers::{Condition, Reg16, Reg8, Registers};
+pub use self::trace::{doctor_line, Tracer};
+use crate::bus::Bus;
+use crate::interrupts::Interrupt;
+use crate::memory::{Memory, HEADER_CHECKSUM_ADDRESS};
+use crate::model::Model;
+use crate::timer::DIV_ADDRESS;
 
+// cycles (4 per M-cycle) taken by a full frame, including VBlank
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+// kind of access done by the cpu during one M-cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusAccess {
+    Read,
+    Write,
+    Internal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusCycle {
+    pub address: u16,
+    pub value: u8,
+    pub access: BusAccess,
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
-pub struct CPU {
-    pub instruction_set: HashMap<u8, instruction::Instruction>,
-    pub memory: Memory,
-    // 8 bits registers
-    pub a: u8,
-    pub b: u8,
-    pub c: u8,
-    pub d: u8,
-    pub e: u8,
-    pub f: u8,
-    pub h: u8,
-    pub l: u8,
-    // 16 bits registers -> combine 8 bits
-    pub sp: usize, // Stack Pointer register points to the current stack position
-    pub pc: usize, // Program Counter register point to the next instruction to be executed in GB memory
-    // cycles
-    cycles: u32,
+pub struct CPU<B: Bus = Memory> {
+    // opcode tables built by `with_bus`, shared with the clones of this cpu
+    pub instruction_set: Arc<InstructionSet<B>>,
+    // everything behind the bus, the whole machine unless a test plugs in something else
+    pub memory: B,
+    pub registers: Registers,
+    // cycles elapsed since power on, never wraps
+    cycles: u64,
+    // set when the ppu enters VBlank, see run_until_frame
+    vblank: bool,
+    // set by conditional instructions when their condition holds
+    branch_taken: bool,
+    // interrupt master enable
+    pub ime: bool,
+    // EI only sets IME after the instruction following it
+    ime_scheduled: bool,
+    // low power states entered by HALT and STOP
+    pub halted: bool,
+    pub stopped: bool,
+    // HALT bug: the byte after HALT is fetched twice
+    halt_bug: bool,
+    // logs every executed instruction
+    pub tracer: Option<Tracer>,
+    // the last executed instructions, to be shown when an error is returned
+    pub history: Option<History>,
+    // emulate the hardware lock up on illegal opcodes instead of returning an error
+    pub lock_on_illegal: bool,
+    pub locked: bool,
+    // when set, every M-cycle is recorded here
+    pub bus_log: Option<Vec<BusCycle>>,
 }
 
 impl CPU {
     pub fn new() -> CPU {
-        let mut cpu: CPU = CPU {
-            instruction_set: HashMap::new(),
-            // registers: Registers::new(),
-            memory: Memory::new(),
-            a: 0,
-            b: 0,
-            c: 0,
-            d: 0,
-            e: 0,
-            f: 0,
-            h: 0,
-            l: 0,
-            sp: 0,
-            pc: 0,
+        CPU::with_bus(Memory::new())
+    }
+
+    // puts the cpu and IO registers in the state the boot rom of `model` leaves them in,
+    // the cartridge must already be loaded since DMG flags depend on its header checksum
+    pub fn skip_boot_rom(&mut self, model: Model) {
+        let header_checksum = self.memory.read_bytes(HEADER_CHECKSUM_ADDRESS);
+        self.memory.model = model;
+        self.memory.unmap_boot_rom();
+        self.registers = model.post_boot_registers(header_checksum);
+        for (address, value) in model.post_boot_io() {
+            self.memory.poke(address, value);
+        }
+        self.memory.timer.set_div(model.post_boot_div());
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    pub fn with_bus(memory: B) -> CPU<B> {
+        CPU {
+            instruction_set: Arc::new(instruction_set()),
+            registers: Registers::new(),
+            memory,
             cycles: 0,
+            vblank: false,
+            branch_taken: false,
+            ime: false,
+            ime_scheduled: false,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            tracer: None,
+            history: None,
+            lock_on_illegal: false,
+            locked: false,
+            bus_log: None,
+        }
+    }
+
+    // decodes the instruction at `address` without any side effect on the machine
+    pub fn decode_at(&self, address: u16) -> DecodedInstruction {
+        let bytes: Vec<u8> = (0..3)
+            .map(|offset| self.memory.read(address.wrapping_add(offset)))
+            .collect();
+        decode(&bytes, address)
+    }
+
+    // cycles elapsed since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // executes one instruction (or services an interrupt) and returns the cycles it took
+    pub fn step(&mut self) -> Result<u64, CpuError> {
+        let start = self.cycles;
+        self.execute_instruction()?;
+        Ok(self.cycles - start)
+    }
+
+    // runs whole instructions until at least `cycles` cycles elapsed, returns the cycles run
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<u64, CpuError> {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    // runs until the ppu enters VBlank, which ends the frame being drawn
+    // with the LCD off there is no VBlank, a frame worth of cycles is run instead
+    pub fn run_until_frame(&mut self) -> Result<u64, CpuError> {
+        let start = self.cycles;
+        self.vblank = false;
+        while !self.vblank && self.cycles - start < CYCLES_PER_FRAME {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    // steps until the predicate holds after an instruction, returns the cycles run
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<u64, CpuError>
+    where
+        F: FnMut(&CPU<B>) -> bool,
+    {
+        let start = self.cycles;
+        loop {
+            self.step()?;
+            if predicate(self) {
+                return Ok(self.cycles - start);
+            }
+        }
+    }
+
+    pub fn execute_instruction(&mut self) -> Result<(), CpuError> {
+        if self.locked {
+            // nothing but a reset gets the cpu out of this state
+            self.internal_cycle();
+            return Ok(());
+        }
+
+        if self.stopped {
+            // only a joypad input brings the cpu out of STOP, even if the interrupt is disabled
+            if self.memory.requested_interrupts() & Interrupt::Joypad.bit() == 0 {
+                self.internal_cycle();
+                return Ok(());
+            }
+            self.stopped = false;
+        }
+
+        if self.halted {
+            // HALT ends as soon as an interrupt is pending, whether IME is set or not
+            if self.memory.pending_interrupts() == 0 {
+                self.internal_cycle();
+                return Ok(());
+            }
+            self.halted = false;
+        }
+
+        if self.handle_interrupts() {
+            return Ok(());
+        }
+
+        if let Some(tracer) = &self.tracer {
+            tracer.trace(self);
+        }
+        if self.history.is_some() {
+            self.record_history();
+        }
+
+        let enable_ime = self.ime_scheduled;
+        let address = self.registers.pc;
+        let start = self.cycles;
+        let mut op_code = self.read_next_opcode();
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        }
+        let mut instructions = &self.instruction_set.instructions;
+        let prefixed = op_code == 0xCB;
+        if prefixed {
+            // the prefixed table cycles already include the prefix fetch
+            op_code = self.read_next_opcode();
+            instructions = &self.instruction_set.cb_instructions;
+        }
+        let inst = match instructions[op_code as usize] {
+            Some(inst) => inst,
+            None => return self.missing_instruction(op_code, prefixed, address),
         };
+        self.branch_taken = false;
+        (inst.execute)(self);
+        // every bus access and internal delay already advanced the cycles count,
+        // make sure it matches the documented duration of the instruction
+        debug_assert_eq!(
+            self.cycles - start,
+            if self.branch_taken {
+                inst.cycles_taken
+            } else {
+                inst.cycles
+            } as u64,
+            "wrong cycle count for {}",
+            inst._str
+        );
+
+        // a DI right after EI cancels the pending enable
+        if enable_ime && self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+        Ok(())
+    }
 
-        // LD nn,n
-        cpu.store_instructions(0x06, 8, "LD B, n", |cpu| cpu.nn_n("b"));
-        cpu.store_instructions(0x0E, 8, "LD C, n", |cpu| cpu.nn_n("c"));
-        cpu.store_instructions(0x16, 8, "LD D, n", |cpu| cpu.nn_n("d"));
-        cpu.store_instructions(0x1E, 8, "LD E, n", |cpu| cpu.nn_n("e"));
-        cpu.store_instructions(0x26, 8, "LD H, n", |cpu| cpu.nn_n("h"));
-        cpu.store_instructions(0x2E, 8, "LD L, n", |cpu| cpu.nn_n("l"));
-        cpu.store_instructions(0x36, 12, "LD (HL), n", |cpu| {
-            let n = cpu.read_next_opcode();
-            cpu.memory
-                .write_bytes(cpu.get_register_value_u16("hl") as usize, n);
-        });
-
-        // LD r1,r2
-        // A
-        cpu.store_instructions(0x7F, 4, "LD A, A", |cpu| cpu.r2_to_r1("a", "a"));
-        cpu.store_instructions(0x78, 4, "LD A, B", |cpu| cpu.r2_to_r1("a", "b"));
-        cpu.store_instructions(0x79, 4, "LD A, C", |cpu| cpu.r2_to_r1("a", "c"));
-        cpu.store_instructions(0x7A, 4, "LD A, D", |cpu| cpu.r2_to_r1("a", "d"));
-        cpu.store_instructions(0x7B, 4, "LD A, E", |cpu| cpu.r2_to_r1("a", "e"));
-        cpu.store_instructions(0x7C, 4, "LD A, H", |cpu| cpu.r2_to_r1("a", "h"));
-        cpu.store_instructions(0x7D, 4, "LD A, L", |cpu| cpu.r2_to_r1("a", "l"));
-        cpu.store_instructions(0x7E, 8, "LD A, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("a", value);
-        });
-        // B
-        cpu.store_instructions(0x40, 4, "LD B, B", |cpu| cpu.r2_to_r1("b", "b"));
-        cpu.store_instructions(0x41, 4, "LD B, C", |cpu| cpu.r2_to_r1("b", "c"));
-        cpu.store_instructions(0x42, 4, "LD B, D", |cpu| cpu.r2_to_r1("b", "d"));
-        cpu.store_instructions(0x43, 4, "LD B, E", |cpu| cpu.r2_to_r1("b", "e"));
-        cpu.store_instructions(0x44, 4, "LD B, H", |cpu| cpu.r2_to_r1("b", "h"));
-        cpu.store_instructions(0x45, 4, "LD B, L", |cpu| cpu.r2_to_r1("b", "l"));
-        cpu.store_instructions(0x46, 8, "LD B, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("b", value);
-        });
-        // C
-        cpu.store_instructions(0x48, 4, "LD C, B", |cpu| cpu.r2_to_r1("c", "b"));
-        cpu.store_instructions(0x49, 4, "LD C, C", |cpu| cpu.r2_to_r1("c", "c"));
-        cpu.store_instructions(0x4A, 4, "LD C, D", |cpu| cpu.r2_to_r1("c", "d"));
-        cpu.store_instructions(0x4B, 4, "LD C, E", |cpu| cpu.r2_to_r1("c", "e"));
-        cpu.store_instructions(0x4C, 4, "LD C, H", |cpu| cpu.r2_to_r1("c", "h"));
-        cpu.store_instructions(0x4D, 4, "LD C, L", |cpu| cpu.r2_to_r1("c", "l"));
-        cpu.store_instructions(0x4E, 8, "LD C, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("c", value);
-        });
-        // D
-        cpu.store_instructions(0x50, 4, "LD D, B", |cpu| cpu.r2_to_r1("d", "b"));
-        cpu.store_instructions(0x51, 4, "LD D, C", |cpu| cpu.r2_to_r1("d", "c"));
-        cpu.store_instructions(0x52, 4, "LD D, D", |cpu| cpu.r2_to_r1("d", "d"));
-        cpu.store_instructions(0x53, 4, "LD D, E", |cpu| cpu.r2_to_r1("d", "e"));
-        cpu.store_instructions(0x54, 4, "LD D, H", |cpu| cpu.r2_to_r1("d", "h"));
-        cpu.store_instructions(0x55, 4, "LD D, L", |cpu| cpu.r2_to_r1("d", "l"));
-        cpu.store_instructions(0x56, 8, "LD D, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("d", value);
-        });
-        // E
-        cpu.store_instructions(0x58, 4, "LD E, B", |cpu| cpu.r2_to_r1("e", "b"));
-        cpu.store_instructions(0x59, 4, "LD E, C", |cpu| cpu.r2_to_r1("e", "c"));
-        cpu.store_instructions(0x5A, 4, "LD E, D", |cpu| cpu.r2_to_r1("e", "d"));
-        cpu.store_instructions(0x5B, 4, "LD E, E", |cpu| cpu.r2_to_r1("e", "e"));
-        cpu.store_instructions(0x5C, 4, "LD E, H", |cpu| cpu.r2_to_r1("e", "h"));
-        cpu.store_instructions(0x5D, 4, "LD E, L", |cpu| cpu.r2_to_r1("e", "l"));
-        cpu.store_instructions(0x5E, 8, "LD E, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("e", value);
-        });
-        // H
-        cpu.store_instructions(0x60, 4, "LD H, B", |cpu| cpu.r2_to_r1("h", "b"));
-        cpu.store_instructions(0x61, 4, "LD H, C", |cpu| cpu.r2_to_r1("h", "c"));
-        cpu.store_instructions(0x62, 4, "LD H, D", |cpu| cpu.r2_to_r1("h", "d"));
-        cpu.store_instructions(0x63, 4, "LD H, E", |cpu| cpu.r2_to_r1("h", "e"));
-        cpu.store_instructions(0x64, 4, "LD H, H", |cpu| cpu.r2_to_r1("h", "h"));
-        cpu.store_instructions(0x65, 4, "LD H, L", |cpu| cpu.r2_to_r1("h", "l"));
-        cpu.store_instructions(0x66, 8, "LD H, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("h", value);
-        });
-        // L
-        cpu.store_instructions(0x68, 4, "LD L, B", |cpu| cpu.r2_to_r1("l", "b"));
-        cpu.store_instructions(0x69, 4, "LD L, C", |cpu| cpu.r2_to_r1("l", "c"));
-        cpu.store_instructions(0x6A, 4, "LD L, D", |cpu| cpu.r2_to_r1("l", "d"));
-        cpu.store_instructions(0x6B, 4, "LD L, E", |cpu| cpu.r2_to_r1("l", "e"));
-        cpu.store_instructions(0x6C, 4, "LD L, H", |cpu| cpu.r2_to_r1("l", "h"));
-        cpu.store_instructions(0x6D, 4, "LD L, L", |cpu| cpu.r2_to_r1("l", "l"));
-        cpu.store_instructions(0x6E, 8, "LD L, (HL)", |cpu| {
-            let hl: u16 = cpu.get_register_value_u16("hl");
-            let value = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("l", value);
-        });
-        // HL
-        cpu.store_instructions(0x70, 8, "LD (HL), B", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            let b = cpu
-                .memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("b"));
-        });
-        cpu.store_instructions(0x71, 8, "LD (HL), C", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            cpu.memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("c"));
-        });
-        cpu.store_instructions(0x72, 8, "LD (HL), D", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            cpu.memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("d"));
-        });
-        cpu.store_instructions(0x73, 8, "LD (HL), E", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            cpu.memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("e"));
-        });
-        cpu.store_instructions(0x74, 8, "LD (HL), H", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            cpu.memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("h"));
-        });
-        cpu.store_instructions(0x75, 8, "LD (HL), L", |cpu| {
-            let pointer = cpu.get_register_value_u16("hl");
-            cpu.memory
-                .write_bytes(pointer as usize, cpu.get_register_value_u8("l"));
-        });
-
-        //LD A, n // FIXME do
-        cpu.store_instructions(0x0A, 8, "LD A, (BC)", |cpu| cpu.r2_to_r1("a", "bc"));
-        cpu.store_instructions(0x1A, 8, "LD A, (DE)", |cpu| cpu.r2_to_r1("a", "de"));
-        cpu.store_instructions(0xFA, 16, "LD A, (nn)", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            let value = cpu
-                .memory
-                .read_bytes(u16::from_le_bytes([first_byte, second_byte]) as usize);
-            cpu.set_register_value_u8("a", value);
-        });
-        cpu.store_instructions(0x3E, 8, "LD A, #", |cpu| cpu.r2_to_r1("a", "bc"));
-
-        // LD n, A
-        cpu.store_instructions(0x78, 4, "LD B, A", |cpu| cpu.r2_to_r1("b", "a"));
-        cpu.store_instructions(0x79, 4, "LD C, A", |cpu| cpu.r2_to_r1("c", "a"));
-        cpu.store_instructions(0x7A, 4, "LD D, A", |cpu| cpu.r2_to_r1("d", "a"));
-        cpu.store_instructions(0x7B, 4, "LD E, A", |cpu| cpu.r2_to_r1("e", "a"));
-        cpu.store_instructions(0x7C, 4, "LD H, A", |cpu| cpu.r2_to_r1("h", "a"));
-        cpu.store_instructions(0x7D, 4, "LD L, A", |cpu| cpu.r2_to_r1("l", "a"));
-        cpu.store_instructions(0x02, 8, "LD (BC), A", |cpu| cpu.r2_to_r1("bc", "a"));
-        cpu.store_instructions(0x12, 8, "LD (DE), A", |cpu| cpu.r2_to_r1("de", "a"));
-        cpu.store_instructions(0x77, 8, "LD (HL), A", |cpu| cpu.r2_to_r1("hl", "a"));
-        cpu.store_instructions(0xEA, 16, "LD (nn), A", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.memory.write_bytes(
-                u16::from_le_bytes([first_byte, second_byte]) as usize,
-                cpu.get_register_value_u8("a"),
-            );
-        });
-
-        // LD A, (C)
-        cpu.store_instructions(0xF2, 8, "LD A, (C)", |cpu| cpu.a_c());
-
-        // LD A, (C)
-        cpu.store_instructions(0xE2, 8, "LD (C), A", |cpu| {
-            let c = cpu.get_register_value_u8("c");
-            let a = cpu.get_register_value_u8("a");
-            cpu.memory.write_bytes(0xFF00 + c as usize, a);
-        });
-
-        // LDD A, (HL)
-        cpu.store_instructions(0x3A, 8, "LDD A, (HL)", |cpu| {
-            let hl: u8 = cpu.get_register_value_u8("hl");
-            let a = cpu.get_register_value_u8("a");
-            cpu.memory.write_bytes(hl as usize, a);
-            cpu.set_register_value_u8("hl", hl - 1);
-        });
-
-        // LDD (HL), A
-        cpu.store_instructions(0x32, 8, "LDD (HL), A", |cpu| {
-            let hl = cpu.get_register_value_u8("hl");
-            let value_at_hl = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("a", value_at_hl);
-            cpu.set_register_value_u8("hl", hl - 1);
-        });
-
-        // LDI A, (HL)
-        cpu.store_instructions(0x2A, 8, "LDI A, (HL)", |cpu| {
-            let hl: u8 = cpu.get_register_value_u8("hl");
-            let a = cpu.get_register_value_u8("a");
-            cpu.memory.write_bytes(hl as usize, a);
-            cpu.set_register_value_u8("hl", hl + 1);
-        });
-
-        // LDI (HL), A
-        cpu.store_instructions(0x22, 8, "LDI (HL), A", |cpu| {
-            let hl = cpu.get_register_value_u8("hl");
-            let value_at_hl = cpu.memory.read_bytes(hl as usize);
-            cpu.set_register_value_u8("a", value_at_hl);
-            cpu.set_register_value_u8("hl", hl + 1);
-        });
-
-        // LDH (n), A
-        cpu.store_instructions(0xE0, 12, "LDH (n), A", |cpu| {
-            let a = cpu.get_register_value_u8("a");
-            let n = cpu.read_next_opcode();
-            cpu.memory.write_bytes(0xFF00 + (n as usize), a);
-        });
-
-        // LDH A, (n)
-        cpu.store_instructions(0xF0, 12, "LDH A, (n)", |cpu| {
-            let n = cpu.read_next_opcode();
-            let value = cpu.memory.read_bytes(0xFF00 + (n as usize));
-            cpu.set_register_value_u8("a", value);
-        });
-
-        // LD n, nn
-        cpu.store_instructions(0x01, 12, "LD BC, nn", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.set_register_value_u16("bc", u16::from_le_bytes([first_byte, second_byte]));
-        });
-        cpu.store_instructions(0x11, 12, "LD DE, nn", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.set_register_value_u16("de", u16::from_le_bytes([first_byte, second_byte]));
-        });
-        cpu.store_instructions(0x21, 12, "LD HL, nn", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.set_register_value_u16("hl", u16::from_le_bytes([first_byte, second_byte]));
-        });
-        cpu.store_instructions(0x31, 12, "LD SP, nn", |cpu| {
-            let first_byte: u8 = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.set_register_value_u16("sp", u16::from_le_bytes([first_byte, second_byte]));
-        });
-
-        // LD SP, HL
-        cpu.store_instructions(0xF9, 8, "LD SP, HL", |cpu| {
-            let hl = cpu.get_register_value_u16("hl");
-            cpu.set_register_value_u16("sp", hl);
-        });
-
-        // LDHL SP, n
-        cpu.store_instructions(0xF8, 12, "LDHL SP, n", |cpu| {
-            let sn = i16::from_le_bytes([cpu.read_next_opcode(), 0x00]); //signed value
-            cpu.set_register_value_u16("hl", (cpu.sp as i16).wrapping_add(sn) as u16);
-            // try to avoid any overflow
-            unimplemented!(); // FIXME add flag reset
-                              // #define CARRY_S(n, n1, n2)     ((((n) < (n1)) | ((n) < (n2))) << 4)
-                              // let n1 = cpu.get_register_value_u16("hl").to_le_bytes()[1];
-                              // let n2 = cpu.get_register_value_u16("sp").to_le_bytes()[1];
-                              // let carry_s: <bool as Shl<i32>>::Output = ((((n1) < (n2)) | ((n1) < (sn))) << 4);
-        });
-
-        // LD (nn), SP
-        cpu.store_instructions(0x08, 20, "LD (nn), SP", |cpu| {
-            let first_byte = cpu.read_next_opcode();
-            let second_byte = cpu.read_next_opcode();
-            cpu.memory.write_bytes(
-                u16::from_le_bytes([first_byte, second_byte]) as usize,
-                cpu.get_register_value_u16("sp").to_le_bytes()[1],
-            );
-        });
-
-        // PUSH nn
-        cpu.store_instructions(0xF5, 16, "PUSH AF", |cpu| cpu.push_stack("af"));
-        cpu.store_instructions(0xC5, 16, "PUSH BC", |cpu| cpu.push_stack("bc"));
-        cpu.store_instructions(0xD5, 16, "PUSH DE", |cpu| cpu.push_stack("de"));
-        cpu.store_instructions(0xE5, 16, "PUSH HL", |cpu| cpu.push_stack("hl"));
-
-        // POP nn
-        cpu.store_instructions(0xF1, 12, "POP AF", |cpu| cpu.pop_stack("af"));
-        cpu.store_instructions(0xC1, 12, "POP BC", |cpu| cpu.pop_stack("bc"));
-        cpu.store_instructions(0xD1, 12, "POP DE", |cpu| cpu.pop_stack("de"));
-        cpu.store_instructions(0xE1, 12, "POP HL", |cpu| cpu.pop_stack("hl"));
-
-        // ADD A, n
-        // cpu.store_instructions(0x87, 4, "ADD A, A", |cpu| cpu.add())
-
-        return cpu;
-    }
-
-    pub fn store_instructions(
+    #[cold]
+    fn missing_instruction(
         &mut self,
         op_code: u8,
-        cycles: u8,
-        _str: &str,
-        execute: fn(&mut CPU),
-    ) {
-        self.instruction_set
-            .insert(op_code, Instruction::new(op_code, cycles, _str, execute));
-    }
-
-    pub fn execute_instruction(&mut self) {
-        let op_code = self.memory.read_bytes(self.pc);
-        println!("read_operation -> {:#04X?} at {}", op_code, self.pc);
-        let inst = self.instruction_set.get(&op_code).unwrap().clone(); // FIXME try to avoid the clone here
-        (inst.execute)(self);
-        // Increment cpu counter here
-        // TODO possibly wrong
-        self.pc += 1;
-        // increment cycles count
-        self.cycles += inst.cycles as u32;
+        prefixed: bool,
+        pc: u16,
+    ) -> Result<(), CpuError> {
+        let bank = self.memory.rom_bank(pc);
+        if prefixed || !ILLEGAL_OP_CODES.contains(&op_code) {
+            return Err(CpuError::UnknownOpcode {
+                op_code,
+                prefixed,
+                pc,
+                bank,
+            });
+        }
+
+        if self.lock_on_illegal {
+            self.locked = true;
+            return Ok(());
+        }
+        Err(CpuError::IllegalOpcode { op_code, pc, bank })
     }
 
-    pub fn read_next_opcode(&mut self) -> u8 {
-        self.pc += 1;
-        let op_code = self.memory.mem_space[self.pc];
-        return op_code;
-    }
-
-    pub fn get_register_value_u8(&self, register_name: &str) -> u8 {
-        match register_name {
-            "a" => self.a,
-            "b" => self.b,
-            "c" => self.c,
-            "d" => self.d,
-            "e" => self.e,
-            "f" => self.f,
-            "h" => self.h,
-            "l" => self.l,
-            _ => panic!("unknown cpu u8 register name"),
+    fn record_history(&mut self) {
+        let pc = self.registers.pc;
+        let mut bytes = [0; 3];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.memory.read(pc.wrapping_add(offset as u16));
+        }
+        let entry = HistoryEntry {
+            pc,
+            bank: self.memory.rom_bank(pc),
+            bytes,
+            registers: self.registers.clone(),
+        };
+        if let Some(history) = &mut self.history {
+            history.record(entry);
         }
     }
 
-    pub fn get_register_value_u16(&self, register_name: &str) -> u16 {
-        match register_name {
-            "af" => u16::from_le_bytes([
-                self.get_register_value_u8("a"),
-                self.get_register_value_u8("f"),
-            ]),
-            "bc" => u16::from_le_bytes([
-                self.get_register_value_u8("b"),
-                self.get_register_value_u8("c"),
-            ]),
-            "de" => u16::from_le_bytes([
-                self.get_register_value_u8("d"),
-                self.get_register_value_u8("e"),
-            ]),
-            "hl" => u16::from_le_bytes([
-                self.get_register_value_u8("h"),
-                self.get_register_value_u8("l"),
-            ]),
-            _ => panic!("unknown  cpu u16 register name"),
+    // services the highest priority pending interrupt if IME is set
+    // returns true when an interrupt was dispatched instead of an instruction
+    pub fn handle_interrupts(&mut self) -> bool {
+        if !self.ime {
+            return false;
         }
+
+        let pending = self.memory.pending_interrupts();
+        let interrupt = match Interrupt::ALL.iter().find(|i| pending & i.bit() != 0) {
+            Some(interrupt) => *interrupt,
+            None => return false,
+        };
+
+        // 2 wait states, pc pushed on the stack, then the jump to the vector
+        self.ime = false;
+        self.memory.clear_interrupt(interrupt);
+        self.internal_cycle();
+        self.internal_cycle();
+        self.push_u16(self.registers.pc);
+        self.registers.pc = interrupt.vector();
+        true
     }
 
-    pub fn set_register_value_u8(&mut self, register_name: &str, value: u8) {
-        match register_name {
-            "a" => self.a = value,
-            "b" => self.b = value,
-            "c" => self.c = value,
-            "d" => self.d = value,
-            "e" => self.e = value,
-            "f" => self.f = value,
-            "h" => self.h = value,
-            "l" => self.l = value,
-            _ => panic!("unknown cpu u8 register name"),
+    // every bus access takes one M-cycle (4 cycles), during which the rest of the machine runs
+    fn tick(&mut self) {
+        self.cycles += 4;
+        if self.memory.tick() {
+            self.vblank = true;
         }
     }
 
-    pub fn set_register_value_u16(&mut self, register_name: &str, value: u16) {
-        let bytes: [u8; 2] = u16::to_le_bytes(value);
-        match register_name {
-            "af" => {
-                self.set_register_value_u8("a", bytes[0]);
-                self.set_register_value_u8("f", bytes[1]);
-            }
-            "bc" => {
-                self.set_register_value_u8("b", bytes[0]);
-                self.set_register_value_u8("c", bytes[1]);
-            }
-            "de" => {
-                self.set_register_value_u8("d", bytes[0]);
-                self.set_register_value_u8("e", bytes[1]);
-            }
-            "hl" => {
-                self.set_register_value_u8("h", bytes[0]);
-                self.set_register_value_u8("l", bytes[1]);
-            }
-            "sp" => self.sp = value as usize,
-            _ => panic!("unknown cpu u16 register name"),
+    pub fn read_cycle(&mut self, pointer: u16) -> u8 {
+        self.tick();
+        let value = self.memory.read(pointer);
+        self.log_bus_cycle(pointer, value, BusAccess::Read);
+        value
+    }
+
+    pub fn write_cycle(&mut self, pointer: u16, data: u8) {
+        self.tick();
+        self.memory.write(pointer, data);
+        self.log_bus_cycle(pointer, data, BusAccess::Write);
+    }
+
+    // M-cycle spent by the cpu without touching the bus
+    pub fn internal_cycle(&mut self) {
+        self.tick();
+        self.log_bus_cycle(0, 0, BusAccess::Internal);
+    }
+
+    fn log_bus_cycle(&mut self, pointer: u16, value: u8, access: BusAccess) {
+        if let Some(bus_log) = &mut self.bus_log {
+            bus_log.push(BusCycle {
+                address: pointer,
+                value,
+                access,
+            });
         }
     }
 
-    pub fn nn_n(&mut self, register_name: &str) {
+    // pc always points to the next byte to be read
+    pub fn read_next_opcode(&mut self) -> u8 {
+        let op_code = self.read_cycle(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        op_code
+    }
+
+    pub fn nn_n(&mut self, register: Reg8) {
         let value = self.read_next_opcode();
-        self.set_register_value_u8(register_name, value);
+        self.registers.set_register_value_u8(register, value);
     }
 
     pub fn a_c(&mut self) {
-        let c = self.get_register_value_u8("c");
-        self.set_register_value_u8("a", self.memory.read_bytes(0xFF00 + c as usize));
+        let c = self.registers.c;
+        self.registers.a = self.read_cycle(0xFF00 | c as u16);
+    }
+
+    // LD A, (rr)
+    pub fn ld_a_from(&mut self, register: Reg16) {
+        let pointer = self.registers.get_register_value_u16(register);
+        self.registers.a = self.read_cycle(pointer);
+    }
+
+    // LD (rr), A
+    pub fn ld_a_to(&mut self, register: Reg16) {
+        let pointer = self.registers.get_register_value_u16(register);
+        self.write_cycle(pointer, self.registers.a);
     }
 
-    pub fn r2_to_r1(&mut self, r1: &str, r2: &str) {
-        let r2_value = self.get_register_value_u8(r2);
-        self.set_register_value_u8(r1, r2_value);
+    // the high byte is pushed first so the value ends up little endian in memory
+    // sp is decremented during an internal cycle before the writes
+    pub fn push_u16(&mut self, value: u16) {
+        let bytes = value.to_be_bytes();
+        self.internal_cycle();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write_cycle(self.registers.sp, bytes[0]);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write_cycle(self.registers.sp, bytes[1]);
     }
 
-    pub fn push_stack(&mut self, register_name: &str) {
-        let value = self.get_register_value_u16(register_name);
-        self.sp -= 1;
-        self.memory.write_bytes(self.sp, value.to_le_bytes()[0]);
-        self.sp -= 1;
-        self.memory.write_bytes(self.sp, value.to_le_bytes()[1]);
+    pub fn pop_u16(&mut self) -> u16 {
+        let low = self.read_cycle(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        let high = self.read_cycle(self.registers.sp);
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
     }
 
-    pub fn pop_stack(&mut self, register_name: &str) {
-        let value = self.get_register_value_u16(register_name);
-        self.sp += 1;
-        self.memory.write_bytes(self.sp, value.to_le_bytes()[0]);
-        self.sp += 1;
-        self.memory.write_bytes(self.sp, value.to_le_bytes()[1]);
+    pub fn push_stack(&mut self, register: Reg16) {
+        let value = self.registers.get_register_value_u16(register);
+        self.push_u16(value);
     }
 
-    pub fn registers_to_string(&self) -> String {
-        return format!("Registers state:\n(a: {}) (b: {}) c: {}) (d: {})\n(e: {}) (f: {}) (h: {}) (l: {})\n(sp: {}) (pc: {})",
-            self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, self.sp, self.pc);
+    pub fn pop_stack(&mut self, register: Reg16) {
+        let value = self.pop_u16();
+        self.registers.set_register_value_u16(register, value);
     }
+}
+
+// cpu running `program` from 0x0000 with the stack at the top of HRAM
+#[cfg(test)]
+pub(crate) fn test_cpu(program: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.memory.load_rom(program);
+    cpu.registers.sp = 0xFFFE;
+    cpu
+}
+
+// fills the opcode tables, done once by every new cpu
+pub fn instruction_set<B: Bus>() -> InstructionSet<B> {
+    let mut set = InstructionSet::new();
+    register_instructions(&mut set);
+    // CB prefix
+    cb::register_cb_instructions(&mut set);
+    set
+}
+
+fn register_instructions<B: Bus>(set: &mut InstructionSet<B>) {
+    // LD nn,n
+    set.store_instructions(0x06, 8, "LD B, n", |cpu| cpu.nn_n(Reg8::B));
+    set.store_instructions(0x0E, 8, "LD C, n", |cpu| cpu.nn_n(Reg8::C));
+    set.store_instructions(0x16, 8, "LD D, n", |cpu| cpu.nn_n(Reg8::D));
+    set.store_instructions(0x1E, 8, "LD E, n", |cpu| cpu.nn_n(Reg8::E));
+    set.store_instructions(0x26, 8, "LD H, n", |cpu| cpu.nn_n(Reg8::H));
+    set.store_instructions(0x2E, 8, "LD L, n", |cpu| cpu.nn_n(Reg8::L));
+    set.store_instructions(0x36, 12, "LD (HL), n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.write_hl(n);
+    });
+
+    // LD r1,r2
+    // A
+    set.store_instructions(0x7F, 4, "LD A, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::A)
+    });
+    set.store_instructions(0x78, 4, "LD A, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::B)
+    });
+    set.store_instructions(0x79, 4, "LD A, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::C)
+    });
+    set.store_instructions(0x7A, 4, "LD A, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::D)
+    });
+    set.store_instructions(0x7B, 4, "LD A, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::E)
+    });
+    set.store_instructions(0x7C, 4, "LD A, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::H)
+    });
+    set.store_instructions(0x7D, 4, "LD A, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::A, Reg8::L)
+    });
+    set.store_instructions(0x7E, 8, "LD A, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.a = value;
+    });
+    // B
+    set.store_instructions(0x40, 4, "LD B, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::B)
+    });
+    set.store_instructions(0x41, 4, "LD B, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::C)
+    });
+    set.store_instructions(0x42, 4, "LD B, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::D)
+    });
+    set.store_instructions(0x43, 4, "LD B, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::E)
+    });
+    set.store_instructions(0x44, 4, "LD B, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::H)
+    });
+    set.store_instructions(0x45, 4, "LD B, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::L)
+    });
+    set.store_instructions(0x46, 8, "LD B, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.b = value;
+    });
+    // C
+    set.store_instructions(0x48, 4, "LD C, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::B)
+    });
+    set.store_instructions(0x49, 4, "LD C, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::C)
+    });
+    set.store_instructions(0x4A, 4, "LD C, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::D)
+    });
+    set.store_instructions(0x4B, 4, "LD C, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::E)
+    });
+    set.store_instructions(0x4C, 4, "LD C, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::H)
+    });
+    set.store_instructions(0x4D, 4, "LD C, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::L)
+    });
+    set.store_instructions(0x4E, 8, "LD C, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.c = value;
+    });
+    // D
+    set.store_instructions(0x50, 4, "LD D, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::B)
+    });
+    set.store_instructions(0x51, 4, "LD D, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::C)
+    });
+    set.store_instructions(0x52, 4, "LD D, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::D)
+    });
+    set.store_instructions(0x53, 4, "LD D, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::E)
+    });
+    set.store_instructions(0x54, 4, "LD D, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::H)
+    });
+    set.store_instructions(0x55, 4, "LD D, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::L)
+    });
+    set.store_instructions(0x56, 8, "LD D, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.d = value;
+    });
+    // E
+    set.store_instructions(0x58, 4, "LD E, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::B)
+    });
+    set.store_instructions(0x59, 4, "LD E, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::C)
+    });
+    set.store_instructions(0x5A, 4, "LD E, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::D)
+    });
+    set.store_instructions(0x5B, 4, "LD E, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::E)
+    });
+    set.store_instructions(0x5C, 4, "LD E, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::H)
+    });
+    set.store_instructions(0x5D, 4, "LD E, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::L)
+    });
+    set.store_instructions(0x5E, 8, "LD E, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.e = value;
+    });
+    // H
+    set.store_instructions(0x60, 4, "LD H, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::B)
+    });
+    set.store_instructions(0x61, 4, "LD H, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::C)
+    });
+    set.store_instructions(0x62, 4, "LD H, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::D)
+    });
+    set.store_instructions(0x63, 4, "LD H, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::E)
+    });
+    set.store_instructions(0x64, 4, "LD H, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::H)
+    });
+    set.store_instructions(0x65, 4, "LD H, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::L)
+    });
+    set.store_instructions(0x66, 8, "LD H, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.h = value;
+    });
+    // L
+    set.store_instructions(0x68, 4, "LD L, B", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::B)
+    });
+    set.store_instructions(0x69, 4, "LD L, C", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::C)
+    });
+    set.store_instructions(0x6A, 4, "LD L, D", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::D)
+    });
+    set.store_instructions(0x6B, 4, "LD L, E", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::E)
+    });
+    set.store_instructions(0x6C, 4, "LD L, H", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::H)
+    });
+    set.store_instructions(0x6D, 4, "LD L, L", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::L)
+    });
+    set.store_instructions(0x6E, 8, "LD L, (HL)", |cpu| {
+        let hl: u16 = cpu.registers.get_register_value_u16(Reg16::HL);
+        let value = cpu.read_cycle(hl);
+        cpu.registers.l = value;
+    });
+    // HL
+    set.store_instructions(0x70, 8, "LD (HL), B", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.b);
+    });
+    set.store_instructions(0x71, 8, "LD (HL), C", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.c);
+    });
+    set.store_instructions(0x72, 8, "LD (HL), D", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.d);
+    });
+    set.store_instructions(0x73, 8, "LD (HL), E", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.e);
+    });
+    set.store_instructions(0x74, 8, "LD (HL), H", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.h);
+    });
+    set.store_instructions(0x75, 8, "LD (HL), L", |cpu| {
+        let pointer = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(pointer, cpu.registers.l);
+    });
+
+    // LD A, n
+    set.store_instructions(0x0A, 8, "LD A, (BC)", |cpu| cpu.ld_a_from(Reg16::BC));
+    set.store_instructions(0x1A, 8, "LD A, (DE)", |cpu| cpu.ld_a_from(Reg16::DE));
+    set.store_instructions(0xFA, 16, "LD A, (nn)", |cpu| {
+        let first_byte = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        let value = cpu.read_cycle(u16::from_le_bytes([first_byte, second_byte]));
+        cpu.registers.a = value;
+    });
+    set.store_instructions(0x3E, 8, "LD A, n", |cpu| cpu.nn_n(Reg8::A));
+
+    // LD n, A
+    set.store_instructions(0x47, 4, "LD B, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::B, Reg8::A)
+    });
+    set.store_instructions(0x4F, 4, "LD C, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::C, Reg8::A)
+    });
+    set.store_instructions(0x57, 4, "LD D, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::D, Reg8::A)
+    });
+    set.store_instructions(0x5F, 4, "LD E, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::E, Reg8::A)
+    });
+    set.store_instructions(0x67, 4, "LD H, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::H, Reg8::A)
+    });
+    set.store_instructions(0x6F, 4, "LD L, A", |cpu| {
+        cpu.registers.r2_to_r1(Reg8::L, Reg8::A)
+    });
+    set.store_instructions(0x02, 8, "LD (BC), A", |cpu| cpu.ld_a_to(Reg16::BC));
+    set.store_instructions(0x12, 8, "LD (DE), A", |cpu| cpu.ld_a_to(Reg16::DE));
+    set.store_instructions(0x77, 8, "LD (HL), A", |cpu| cpu.ld_a_to(Reg16::HL));
+    set.store_instructions(0xEA, 16, "LD (nn), A", |cpu| {
+        let first_byte = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        cpu.write_cycle(
+            u16::from_le_bytes([first_byte, second_byte]),
+            cpu.registers.a,
+        );
+    });
+
+    // LD A, (C)
+    set.store_instructions(0xF2, 8, "LD A, (C)", |cpu| cpu.a_c());
+
+    // LD A, (C)
+    set.store_instructions(0xE2, 8, "LD (C), A", |cpu| {
+        let c = cpu.registers.c;
+        let a = cpu.registers.a;
+        cpu.write_cycle(0xFF00 | c as u16, a);
+    });
+
+    // LDD A, (HL)
+    set.store_instructions(0x3A, 8, "LDD A, (HL)", |cpu| {
+        let hl = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.registers.a = cpu.read_cycle(hl);
+        cpu.registers
+            .set_register_value_u16(Reg16::HL, hl.wrapping_sub(1));
+    });
+
+    // LDD (HL), A
+    set.store_instructions(0x32, 8, "LDD (HL), A", |cpu| {
+        let hl = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(hl, cpu.registers.a);
+        cpu.registers
+            .set_register_value_u16(Reg16::HL, hl.wrapping_sub(1));
+    });
+
+    // LDI A, (HL)
+    set.store_instructions(0x2A, 8, "LDI A, (HL)", |cpu| {
+        let hl = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.registers.a = cpu.read_cycle(hl);
+        cpu.registers
+            .set_register_value_u16(Reg16::HL, hl.wrapping_add(1));
+    });
+
+    // LDI (HL), A
+    set.store_instructions(0x22, 8, "LDI (HL), A", |cpu| {
+        let hl = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.write_cycle(hl, cpu.registers.a);
+        cpu.registers
+            .set_register_value_u16(Reg16::HL, hl.wrapping_add(1));
+    });
+
+    // LDH (n), A
+    set.store_instructions(0xE0, 12, "LDH (n), A", |cpu| {
+        let a = cpu.registers.a;
+        let n = cpu.read_next_opcode();
+        cpu.write_cycle(0xFF00 | n as u16, a);
+    });
+
+    // LDH A, (n)
+    set.store_instructions(0xF0, 12, "LDH A, (n)", |cpu| {
+        let n = cpu.read_next_opcode();
+        let value = cpu.read_cycle(0xFF00 | n as u16);
+        cpu.registers.a = value;
+    });
+
+    // LD n, nn
+    set.store_instructions(0x01, 12, "LD BC, nn", |cpu| {
+        let first_byte = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        cpu.registers
+            .set_register_value_u16(Reg16::BC, u16::from_le_bytes([first_byte, second_byte]));
+    });
+    set.store_instructions(0x11, 12, "LD DE, nn", |cpu| {
+        let first_byte = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        cpu.registers
+            .set_register_value_u16(Reg16::DE, u16::from_le_bytes([first_byte, second_byte]));
+    });
+    set.store_instructions(0x21, 12, "LD HL, nn", |cpu| {
+        let first_byte = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        cpu.registers
+            .set_register_value_u16(Reg16::HL, u16::from_le_bytes([first_byte, second_byte]));
+    });
+    set.store_instructions(0x31, 12, "LD SP, nn", |cpu| {
+        let first_byte: u8 = cpu.read_next_opcode();
+        let second_byte = cpu.read_next_opcode();
+        cpu.registers
+            .set_register_value_u16(Reg16::SP, u16::from_le_bytes([first_byte, second_byte]));
+    });
+
+    // LD SP, HL
+    set.store_instructions(0xF9, 8, "LD SP, HL", |cpu| {
+        cpu.internal_cycle();
+        let hl = cpu.registers.get_register_value_u16(Reg16::HL);
+        cpu.registers.set_register_value_u16(Reg16::SP, hl);
+    });
+
+    // LDHL SP, n
+    set.store_instructions(0xF8, 12, "LDHL SP, n", |cpu| {
+        let value = cpu.sp_plus_signed_operand();
+        cpu.internal_cycle();
+        cpu.registers.set_register_value_u16(Reg16::HL, value);
+    });
+
+    // LD (nn), SP
+    set.store_instructions(0x08, 20, "LD (nn), SP", |cpu| {
+        let address = cpu.read_next_u16();
+        let [low, high] = cpu
+            .registers
+            .get_register_value_u16(Reg16::SP)
+            .to_le_bytes();
+        cpu.write_cycle(address, low);
+        cpu.write_cycle(address.wrapping_add(1), high);
+    });
+
+    // PUSH nn
+    set.store_instructions(0xF5, 16, "PUSH AF", |cpu| cpu.push_stack(Reg16::AF));
+    set.store_instructions(0xC5, 16, "PUSH BC", |cpu| cpu.push_stack(Reg16::BC));
+    set.store_instructions(0xD5, 16, "PUSH DE", |cpu| cpu.push_stack(Reg16::DE));
+    set.store_instructions(0xE5, 16, "PUSH HL", |cpu| cpu.push_stack(Reg16::HL));
+
+    // POP nn
+    set.store_instructions(0xF1, 12, "POP AF", |cpu| cpu.pop_stack(Reg16::AF));
+    set.store_instructions(0xC1, 12, "POP BC", |cpu| cpu.pop_stack(Reg16::BC));
+    set.store_instructions(0xD1, 12, "POP DE", |cpu| cpu.pop_stack(Reg16::DE));
+    set.store_instructions(0xE1, 12, "POP HL", |cpu| cpu.pop_stack(Reg16::HL));
+
+    // ADD A, n
+    set.store_instructions(0x87, 4, "ADD A, A", |cpu| cpu.add(cpu.registers.a));
+    set.store_instructions(0x80, 4, "ADD A, B", |cpu| cpu.add(cpu.registers.b));
+    set.store_instructions(0x81, 4, "ADD A, C", |cpu| cpu.add(cpu.registers.c));
+    set.store_instructions(0x82, 4, "ADD A, D", |cpu| cpu.add(cpu.registers.d));
+    set.store_instructions(0x83, 4, "ADD A, E", |cpu| cpu.add(cpu.registers.e));
+    set.store_instructions(0x84, 4, "ADD A, H", |cpu| cpu.add(cpu.registers.h));
+    set.store_instructions(0x85, 4, "ADD A, L", |cpu| cpu.add(cpu.registers.l));
+    set.store_instructions(0x86, 8, "ADD A, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.add(value);
+    });
+    set.store_instructions(0xC6, 8, "ADD A, n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.add(n);
+    });
+
+    // ADC A, n
+    set.store_instructions(0x8F, 4, "ADC A, A", |cpu| cpu.adc(cpu.registers.a));
+    set.store_instructions(0x88, 4, "ADC A, B", |cpu| cpu.adc(cpu.registers.b));
+    set.store_instructions(0x89, 4, "ADC A, C", |cpu| cpu.adc(cpu.registers.c));
+    set.store_instructions(0x8A, 4, "ADC A, D", |cpu| cpu.adc(cpu.registers.d));
+    set.store_instructions(0x8B, 4, "ADC A, E", |cpu| cpu.adc(cpu.registers.e));
+    set.store_instructions(0x8C, 4, "ADC A, H", |cpu| cpu.adc(cpu.registers.h));
+    set.store_instructions(0x8D, 4, "ADC A, L", |cpu| cpu.adc(cpu.registers.l));
+    set.store_instructions(0x8E, 8, "ADC A, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.adc(value);
+    });
+    set.store_instructions(0xCE, 8, "ADC A, n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.adc(n);
+    });
+
+    // SUB n
+    set.store_instructions(0x97, 4, "SUB A", |cpu| cpu.sub(cpu.registers.a));
+    set.store_instructions(0x90, 4, "SUB B", |cpu| cpu.sub(cpu.registers.b));
+    set.store_instructions(0x91, 4, "SUB C", |cpu| cpu.sub(cpu.registers.c));
+    set.store_instructions(0x92, 4, "SUB D", |cpu| cpu.sub(cpu.registers.d));
+    set.store_instructions(0x93, 4, "SUB E", |cpu| cpu.sub(cpu.registers.e));
+    set.store_instructions(0x94, 4, "SUB H", |cpu| cpu.sub(cpu.registers.h));
+    set.store_instructions(0x95, 4, "SUB L", |cpu| cpu.sub(cpu.registers.l));
+    set.store_instructions(0x96, 8, "SUB (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.sub(value);
+    });
+    set.store_instructions(0xD6, 8, "SUB n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.sub(n);
+    });
+
+    // SBC A, n
+    set.store_instructions(0x9F, 4, "SBC A, A", |cpu| cpu.sbc(cpu.registers.a));
+    set.store_instructions(0x98, 4, "SBC A, B", |cpu| cpu.sbc(cpu.registers.b));
+    set.store_instructions(0x99, 4, "SBC A, C", |cpu| cpu.sbc(cpu.registers.c));
+    set.store_instructions(0x9A, 4, "SBC A, D", |cpu| cpu.sbc(cpu.registers.d));
+    set.store_instructions(0x9B, 4, "SBC A, E", |cpu| cpu.sbc(cpu.registers.e));
+    set.store_instructions(0x9C, 4, "SBC A, H", |cpu| cpu.sbc(cpu.registers.h));
+    set.store_instructions(0x9D, 4, "SBC A, L", |cpu| cpu.sbc(cpu.registers.l));
+    set.store_instructions(0x9E, 8, "SBC A, (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.sbc(value);
+    });
+    set.store_instructions(0xDE, 8, "SBC A, n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.sbc(n);
+    });
+
+    // AND n
+    set.store_instructions(0xA7, 4, "AND A", |cpu| cpu.and(cpu.registers.a));
+    set.store_instructions(0xA0, 4, "AND B", |cpu| cpu.and(cpu.registers.b));
+    set.store_instructions(0xA1, 4, "AND C", |cpu| cpu.and(cpu.registers.c));
+    set.store_instructions(0xA2, 4, "AND D", |cpu| cpu.and(cpu.registers.d));
+    set.store_instructions(0xA3, 4, "AND E", |cpu| cpu.and(cpu.registers.e));
+    set.store_instructions(0xA4, 4, "AND H", |cpu| cpu.and(cpu.registers.h));
+    set.store_instructions(0xA5, 4, "AND L", |cpu| cpu.and(cpu.registers.l));
+    set.store_instructions(0xA6, 8, "AND (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.and(value);
+    });
+    set.store_instructions(0xE6, 8, "AND n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.and(n);
+    });
+
+    // XOR n
+    set.store_instructions(0xAF, 4, "XOR A", |cpu| cpu.xor(cpu.registers.a));
+    set.store_instructions(0xA8, 4, "XOR B", |cpu| cpu.xor(cpu.registers.b));
+    set.store_instructions(0xA9, 4, "XOR C", |cpu| cpu.xor(cpu.registers.c));
+    set.store_instructions(0xAA, 4, "XOR D", |cpu| cpu.xor(cpu.registers.d));
+    set.store_instructions(0xAB, 4, "XOR E", |cpu| cpu.xor(cpu.registers.e));
+    set.store_instructions(0xAC, 4, "XOR H", |cpu| cpu.xor(cpu.registers.h));
+    set.store_instructions(0xAD, 4, "XOR L", |cpu| cpu.xor(cpu.registers.l));
+    set.store_instructions(0xAE, 8, "XOR (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.xor(value);
+    });
+    set.store_instructions(0xEE, 8, "XOR n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.xor(n);
+    });
+
+    // OR n
+    set.store_instructions(0xB7, 4, "OR A", |cpu| cpu.or(cpu.registers.a));
+    set.store_instructions(0xB0, 4, "OR B", |cpu| cpu.or(cpu.registers.b));
+    set.store_instructions(0xB1, 4, "OR C", |cpu| cpu.or(cpu.registers.c));
+    set.store_instructions(0xB2, 4, "OR D", |cpu| cpu.or(cpu.registers.d));
+    set.store_instructions(0xB3, 4, "OR E", |cpu| cpu.or(cpu.registers.e));
+    set.store_instructions(0xB4, 4, "OR H", |cpu| cpu.or(cpu.registers.h));
+    set.store_instructions(0xB5, 4, "OR L", |cpu| cpu.or(cpu.registers.l));
+    set.store_instructions(0xB6, 8, "OR (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.or(value);
+    });
+    set.store_instructions(0xF6, 8, "OR n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.or(n);
+    });
+
+    // CP n
+    set.store_instructions(0xBF, 4, "CP A", |cpu| cpu.cp(cpu.registers.a));
+    set.store_instructions(0xB8, 4, "CP B", |cpu| cpu.cp(cpu.registers.b));
+    set.store_instructions(0xB9, 4, "CP C", |cpu| cpu.cp(cpu.registers.c));
+    set.store_instructions(0xBA, 4, "CP D", |cpu| cpu.cp(cpu.registers.d));
+    set.store_instructions(0xBB, 4, "CP E", |cpu| cpu.cp(cpu.registers.e));
+    set.store_instructions(0xBC, 4, "CP H", |cpu| cpu.cp(cpu.registers.h));
+    set.store_instructions(0xBD, 4, "CP L", |cpu| cpu.cp(cpu.registers.l));
+    set.store_instructions(0xBE, 8, "CP (HL)", |cpu| {
+        let value = cpu.read_hl();
+        cpu.cp(value);
+    });
+    set.store_instructions(0xFE, 8, "CP n", |cpu| {
+        let n = cpu.read_next_opcode();
+        cpu.cp(n);
+    });
+
+    // INC n
+    set.store_instructions(0x3C, 4, "INC A", |cpu| {
+        cpu.registers.a = cpu.inc(cpu.registers.a)
+    });
+    set.store_instructions(0x04, 4, "INC B", |cpu| {
+        cpu.registers.b = cpu.inc(cpu.registers.b)
+    });
+    set.store_instructions(0x0C, 4, "INC C", |cpu| {
+        cpu.registers.c = cpu.inc(cpu.registers.c)
+    });
+    set.store_instructions(0x14, 4, "INC D", |cpu| {
+        cpu.registers.d = cpu.inc(cpu.registers.d)
+    });
+    set.store_instructions(0x1C, 4, "INC E", |cpu| {
+        cpu.registers.e = cpu.inc(cpu.registers.e)
+    });
+    set.store_instructions(0x24, 4, "INC H", |cpu| {
+        cpu.registers.h = cpu.inc(cpu.registers.h)
+    });
+    set.store_instructions(0x2C, 4, "INC L", |cpu| {
+        cpu.registers.l = cpu.inc(cpu.registers.l)
+    });
+    set.store_instructions(0x34, 12, "INC (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.inc(value);
+        cpu.write_hl(value);
+    });
+
+    // DEC n
+    set.store_instructions(0x3D, 4, "DEC A", |cpu| {
+        cpu.registers.a = cpu.dec(cpu.registers.a)
+    });
+    set.store_instructions(0x05, 4, "DEC B", |cpu| {
+        cpu.registers.b = cpu.dec(cpu.registers.b)
+    });
+    set.store_instructions(0x0D, 4, "DEC C", |cpu| {
+        cpu.registers.c = cpu.dec(cpu.registers.c)
+    });
+    set.store_instructions(0x15, 4, "DEC D", |cpu| {
+        cpu.registers.d = cpu.dec(cpu.registers.d)
+    });
+    set.store_instructions(0x1D, 4, "DEC E", |cpu| {
+        cpu.registers.e = cpu.dec(cpu.registers.e)
+    });
+    set.store_instructions(0x25, 4, "DEC H", |cpu| {
+        cpu.registers.h = cpu.dec(cpu.registers.h)
+    });
+    set.store_instructions(0x2D, 4, "DEC L", |cpu| {
+        cpu.registers.l = cpu.dec(cpu.registers.l)
+    });
+    set.store_instructions(0x35, 12, "DEC (HL)", |cpu| {
+        let value = cpu.read_hl();
+        let value = cpu.dec(value);
+        cpu.write_hl(value);
+    });
+
+    // ADD HL, n
+    set.store_instructions(0x09, 8, "ADD HL, BC", |cpu| {
+        cpu.add_hl(cpu.registers.get_register_value_u16(Reg16::BC))
+    });
+    set.store_instructions(0x19, 8, "ADD HL, DE", |cpu| {
+        cpu.add_hl(cpu.registers.get_register_value_u16(Reg16::DE))
+    });
+    set.store_instructions(0x29, 8, "ADD HL, HL", |cpu| {
+        cpu.add_hl(cpu.registers.get_register_value_u16(Reg16::HL))
+    });
+    set.store_instructions(0x39, 8, "ADD HL, SP", |cpu| {
+        cpu.add_hl(cpu.registers.get_register_value_u16(Reg16::SP))
+    });
+
+    // ADD SP, n
+    set.store_instructions(0xE8, 16, "ADD SP, n", |cpu| {
+        let value = cpu.sp_plus_signed_operand();
+        cpu.internal_cycle();
+        cpu.internal_cycle();
+        cpu.registers.set_register_value_u16(Reg16::SP, value);
+    });
+
+    // INC nn
+    set.store_instructions(0x03, 8, "INC BC", |cpu| cpu.inc_u16(Reg16::BC));
+    set.store_instructions(0x13, 8, "INC DE", |cpu| cpu.inc_u16(Reg16::DE));
+    set.store_instructions(0x23, 8, "INC HL", |cpu| cpu.inc_u16(Reg16::HL));
+    set.store_instructions(0x33, 8, "INC SP", |cpu| cpu.inc_u16(Reg16::SP));
+
+    // DEC nn
+    set.store_instructions(0x0B, 8, "DEC BC", |cpu| cpu.dec_u16(Reg16::BC));
+    set.store_instructions(0x1B, 8, "DEC DE", |cpu| cpu.dec_u16(Reg16::DE));
+    set.store_instructions(0x2B, 8, "DEC HL", |cpu| cpu.dec_u16(Reg16::HL));
+    set.store_instructions(0x3B, 8, "DEC SP", |cpu| cpu.dec_u16(Reg16::SP));
+
+    // JP nn
+    set.store_instructions(0xC3, 16, "JP nn", |cpu| cpu.jp(true));
+
+    // JP cc, nn
+    set.store_branch_instructions(0xC2, 12, 16, "JP NZ, nn", |cpu| {
+        cpu.jp(cpu.registers.condition(Condition::NZ))
+    });
+    set.store_branch_instructions(0xCA, 12, 16, "JP Z, nn", |cpu| {
+        cpu.jp(cpu.registers.condition(Condition::Z))
+    });
+    set.store_branch_instructions(0xD2, 12, 16, "JP NC, nn", |cpu| {
+        cpu.jp(cpu.registers.condition(Condition::NC))
+    });
+    set.store_branch_instructions(0xDA, 12, 16, "JP C, nn", |cpu| {
+        cpu.jp(cpu.registers.condition(Condition::C))
+    });
+
+    // JP (HL)
+    set.store_instructions(0xE9, 4, "JP (HL)", |cpu| {
+        cpu.registers.pc = cpu.registers.get_register_value_u16(Reg16::HL);
+    });
+
+    // JR n
+    set.store_instructions(0x18, 12, "JR n", |cpu| cpu.jr(true));
+
+    // JR cc, n
+    set.store_branch_instructions(0x20, 8, 12, "JR NZ, n", |cpu| {
+        cpu.jr(cpu.registers.condition(Condition::NZ))
+    });
+    set.store_branch_instructions(0x28, 8, 12, "JR Z, n", |cpu| {
+        cpu.jr(cpu.registers.condition(Condition::Z))
+    });
+    set.store_branch_instructions(0x30, 8, 12, "JR NC, n", |cpu| {
+        cpu.jr(cpu.registers.condition(Condition::NC))
+    });
+    set.store_branch_instructions(0x38, 8, 12, "JR C, n", |cpu| {
+        cpu.jr(cpu.registers.condition(Condition::C))
+    });
+
+    // CALL nn
+    set.store_instructions(0xCD, 24, "CALL nn", |cpu| cpu.call(true));
+
+    // CALL cc, nn
+    set.store_branch_instructions(0xC4, 12, 24, "CALL NZ, nn", |cpu| {
+        cpu.call(cpu.registers.condition(Condition::NZ))
+    });
+    set.store_branch_instructions(0xCC, 12, 24, "CALL Z, nn", |cpu| {
+        cpu.call(cpu.registers.condition(Condition::Z))
+    });
+    set.store_branch_instructions(0xD4, 12, 24, "CALL NC, nn", |cpu| {
+        cpu.call(cpu.registers.condition(Condition::NC))
+    });
+    set.store_branch_instructions(0xDC, 12, 24, "CALL C, nn", |cpu| {
+        cpu.call(cpu.registers.condition(Condition::C))
+    });
+
+    // RST n
+    set.store_instructions(0xC7, 16, "RST 00H", |cpu| cpu.rst(0x00));
+    set.store_instructions(0xCF, 16, "RST 08H", |cpu| cpu.rst(0x08));
+    set.store_instructions(0xD7, 16, "RST 10H", |cpu| cpu.rst(0x10));
+    set.store_instructions(0xDF, 16, "RST 18H", |cpu| cpu.rst(0x18));
+    set.store_instructions(0xE7, 16, "RST 20H", |cpu| cpu.rst(0x20));
+    set.store_instructions(0xEF, 16, "RST 28H", |cpu| cpu.rst(0x28));
+    set.store_instructions(0xF7, 16, "RST 30H", |cpu| cpu.rst(0x30));
+    set.store_instructions(0xFF, 16, "RST 38H", |cpu| cpu.rst(0x38));
+
+    // RET
+    set.store_instructions(0xC9, 16, "RET", |cpu| {
+        cpu.registers.pc = cpu.pop_u16();
+        cpu.internal_cycle();
+    });
+
+    // RET cc
+    set.store_branch_instructions(0xC0, 8, 20, "RET NZ", |cpu| {
+        cpu.ret(cpu.registers.condition(Condition::NZ))
+    });
+    set.store_branch_instructions(0xC8, 8, 20, "RET Z", |cpu| {
+        cpu.ret(cpu.registers.condition(Condition::Z))
+    });
+    set.store_branch_instructions(0xD0, 8, 20, "RET NC", |cpu| {
+        cpu.ret(cpu.registers.condition(Condition::NC))
+    });
+    set.store_branch_instructions(0xD8, 8, 20, "RET C", |cpu| {
+        cpu.ret(cpu.registers.condition(Condition::C))
+    });
+
+    // RETI
+    set.store_instructions(0xD9, 16, "RETI", |cpu| {
+        cpu.registers.pc = cpu.pop_u16();
+        cpu.internal_cycle();
+        cpu.ime = true;
+    });
+
+    // DI
+    set.store_instructions(0xF3, 4, "DI", |cpu| {
+        cpu.ime = false;
+        cpu.ime_scheduled = false;
+    });
+
+    // EI
+    set.store_instructions(0xFB, 4, "EI", |cpu| cpu.ime_scheduled = true);
+
+    // HALT
+    set.store_instructions(0x76, 4, "HALT", |cpu| {
+        // with IME off and an interrupt already pending the cpu does not halt,
+        // instead it fails to increment pc on the next fetch
+        if !cpu.ime && cpu.memory.pending_interrupts() != 0 {
+            cpu.halt_bug = true;
+        } else {
+            cpu.halted = true;
+        }
+    });
+
+    // STOP
+    set.store_instructions(0x10, 4, "STOP", |cpu| {
+        // STOP is followed by a padding byte that is skipped without being read
+        cpu.registers.pc = cpu.registers.pc.wrapping_add(1);
+        cpu.memory.write(DIV_ADDRESS as u16, 0);
+        cpu.stopped = true;
+    });
+
+    // NOP
+    set.store_instructions(0x00, 4, "NOP", |_cpu| {});
+
+    // DAA
+    set.store_instructions(0x27, 4, "DAA", |cpu| cpu.daa());
+
+    // CPL
+    set.store_instructions(0x2F, 4, "CPL", |cpu| {
+        cpu.registers.a = !cpu.registers.a;
+        cpu.registers.f.set_n(true);
+        cpu.registers.f.set_h(true);
+    });
+
+    // SCF
+    set.store_instructions(0x37, 4, "SCF", |cpu| {
+        cpu.registers.f.set_n(false);
+        cpu.registers.f.set_h(false);
+        cpu.registers.f.set_c(true);
+    });
+
+    // CCF
+    set.store_instructions(0x3F, 4, "CCF", |cpu| {
+        cpu.registers.f.set_n(false);
+        cpu.registers.f.set_h(false);
+        cpu.registers.f.set_c(!cpu.registers.f.c());
+    });
+
+    // Accumulator rotates, same as their CB counterparts except Z is always reset
+    set.store_instructions(0x07, 4, "RLCA", |cpu| {
+        cpu.registers.a = cpu.rlc(cpu.registers.a);
+        cpu.registers.f.set_z(false);
+    });
+    set.store_instructions(0x17, 4, "RLA", |cpu| {
+        cpu.registers.a = cpu.rl(cpu.registers.a);
+        cpu.registers.f.set_z(false);
+    });
+    set.store_instructions(0x0F, 4, "RRCA", |cpu| {
+        cpu.registers.a = cpu.rrc(cpu.registers.a);
+        cpu.registers.f.set_z(false);
+    });
+    set.store_instructions(0x1F, 4, "RRA", |cpu| {
+        cpu.registers.a = cpu.rr(cpu.registers.a);
+        cpu.registers.f.set_z(false);
+    });
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
+    }
+}
+
+impl<B: Bus> fmt::Display for CPU<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CPU | {}", self.registers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_cpu, BusAccess, BusCycle, Reg16, CYCLES_PER_FRAME};
+    use crate::bus::Bus;
+    use crate::interrupts::{Interrupt, IE_ADDRESS, IF_ADDRESS};
+    use crate::memory::LCDC_ADDRESS;
+    use crate::model::Model;
+    use crate::timer::DIV_ADDRESS;
+
+    fn request(cpu: &mut super::CPU, interrupt: Interrupt) {
+        cpu.memory.write_bytes(IE_ADDRESS, 0x1F);
+        cpu.memory.request_interrupt(interrupt);
+    }
+
+    #[test]
+    fn interrupt_dispatch_takes_five_cycles() {
+        let mut cpu = test_cpu(&[0x00]);
+        cpu.ime = true;
+        cpu.registers.pc = 0x1234;
+        request(&mut cpu, Interrupt::Timer);
+
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.registers.pc, Interrupt::Timer.vector());
+        assert_eq!(cpu.registers.sp, 0xFFFC);
+        assert_eq!(cpu.memory.read_bytes(0xFFFC), 0x34);
+        assert_eq!(cpu.memory.read_bytes(0xFFFD), 0x12);
+        assert!(!cpu.ime);
+        assert_eq!(cpu.memory.requested_interrupts(), 0);
+    }
+
+    #[test]
+    fn highest_priority_interrupt_is_serviced_first() {
+        let mut cpu = test_cpu(&[0x00]);
+        cpu.ime = true;
+        request(&mut cpu, Interrupt::Joypad);
+        request(&mut cpu, Interrupt::Stat);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, Interrupt::Stat.vector());
+        assert_eq!(cpu.memory.requested_interrupts(), Interrupt::Joypad.bit());
+    }
+
+    #[test]
+    fn interrupts_wait_for_ime() {
+        let mut cpu = test_cpu(&[0x00]);
+        request(&mut cpu, Interrupt::VBlank);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.registers.pc, 0x0001);
+    }
+
+    #[test]
+    fn ei_enables_interrupts_after_the_next_instruction() {
+        // EI ; NOP ; NOP
+        let mut cpu = test_cpu(&[0xFB, 0x00, 0x00]);
+        request(&mut cpu, Interrupt::VBlank);
+
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+        // the instruction following EI still runs before the dispatch
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, 0x0002);
+        assert!(cpu.ime);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, Interrupt::VBlank.vector());
+    }
+
+    #[test]
+    fn di_right_after_ei_cancels_it() {
+        // EI ; DI ; NOP
+        let mut cpu = test_cpu(&[0xFB, 0xF3, 0x00]);
+        request(&mut cpu, Interrupt::VBlank);
+        cpu.run_for_cycles(12).unwrap();
+        assert!(!cpu.ime);
+        assert_eq!(cpu.registers.pc, 0x0003);
+    }
+
+    #[test]
+    fn halt_waits_for_an_interrupt_without_servicing_it_when_ime_is_off() {
+        // HALT ; INC A
+        let mut cpu = test_cpu(&[0x76, 0x3C]);
+        cpu.step().unwrap();
+        assert!(cpu.halted);
+        cpu.run_for_cycles(400).unwrap();
+        assert!(cpu.halted);
+        assert_eq!(cpu.registers.pc, 0x0001);
+
+        request(&mut cpu, Interrupt::Serial);
+        cpu.step().unwrap();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.registers.pc, 0x0002);
+    }
+
+    #[test]
+    fn halt_with_ime_services_the_interrupt_on_wake_up() {
+        // HALT
+        let mut cpu = test_cpu(&[0x76]);
+        cpu.ime = true;
+        cpu.step().unwrap();
+        request(&mut cpu, Interrupt::VBlank);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, Interrupt::VBlank.vector());
+        // the return address is the instruction after HALT
+        assert_eq!(cpu.memory.read_bytes(0xFFFC), 0x01);
+    }
+
+    #[test]
+    fn halt_bug_reads_the_next_byte_twice() {
+        // HALT ; INC A ; NOP
+        let mut cpu = test_cpu(&[0x76, 0x3C, 0x00]);
+        request(&mut cpu, Interrupt::VBlank);
+        cpu.step().unwrap();
+        assert!(!cpu.halted);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, 0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 2);
+        assert_eq!(cpu.registers.pc, 0x0002);
+    }
+
+    #[test]
+    fn stop_resets_div_and_waits_for_the_joypad() {
+        // STOP ; padding ; INC A
+        let mut cpu = test_cpu(&[0x10, 0x00, 0x3C]);
+        cpu.memory.timer.set_div(0x40);
+        cpu.step().unwrap();
+        assert!(cpu.stopped);
+        assert_eq!(cpu.registers.pc, 0x0002);
+        assert_eq!(cpu.memory.read_bytes(DIV_ADDRESS), 0x00);
+
+        // other interrupts do not end STOP, even when enabled
+        request(&mut cpu, Interrupt::Timer);
+        cpu.step().unwrap();
+        assert!(cpu.stopped);
+
+        cpu.memory.request_interrupt(Interrupt::Joypad);
+        cpu.step().unwrap();
+        assert!(!cpu.stopped);
+    }
+
+    #[test]
+    fn bus_accesses_are_logged_in_order() {
+        // CALL $1234
+        let mut cpu = test_cpu(&[0xCD, 0x34, 0x12]);
+        cpu.bus_log = Some(Vec::new());
+        cpu.step().unwrap();
+        let cycle = |address, value, access| BusCycle {
+            address,
+            value,
+            access,
+        };
+        assert_eq!(
+            cpu.bus_log.unwrap(),
+            vec![
+                cycle(0x0000, 0xCD, BusAccess::Read),
+                cycle(0x0001, 0x34, BusAccess::Read),
+                cycle(0x0002, 0x12, BusAccess::Read),
+                cycle(0x0000, 0x00, BusAccess::Internal),
+                cycle(0xFFFD, 0x00, BusAccess::Write),
+                cycle(0xFFFC, 0x03, BusAccess::Write),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_timer_runs_during_the_instruction() {
+        // LDH ($04),A resets DIV on its last M-cycle, then NOPs until
+        // LDH A,($04) reads DIV on its own last M-cycle
+        let program = |nops: usize| {
+            let mut program = vec![0xE0, 0x04];
+            program.extend(vec![0x00; nops]);
+            program.extend([0xF0, 0x04]);
+            program
+        };
+
+        // the read happens 63 M-cycles after the reset, DIV is still 0
+        let mut cpu = test_cpu(&program(60));
+        cpu.run_until(|cpu| cpu.registers.pc == 64).unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+
+        // 64 M-cycles: DIV turns 1 on the read, even though it was still 0 when
+        // the instruction started and its opcode and operand were fetched
+        let mut cpu = test_cpu(&program(61));
+        cpu.run_until(|cpu| cpu.registers.pc == 65).unwrap();
+        assert_eq!(cpu.registers.a, 0x01);
+    }
+
+    #[test]
+    fn every_m_cycle_advances_the_clock_by_four() {
+        // LD (HL),n ; PUSH BC
+        let mut cpu = test_cpu(&[0x36, 0x42, 0xC5]);
+        cpu.registers.set_register_value_u16(Reg16::HL, 0xC000);
+        cpu.bus_log = Some(Vec::new());
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.bus_log.as_ref().unwrap().len(), 7);
+        assert_eq!(cpu.cycles(), 28);
+    }
+
+    #[test]
+    fn step_returns_the_cycles_of_the_instruction() {
+        // NOP ; LD BC,nn ; CALL nn
+        let mut cpu = test_cpu(&[0x00, 0x01, 0x34, 0x12, 0xCD, 0x00, 0x00]);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.step().unwrap(), 24);
+        assert_eq!(cpu.cycles(), 40);
+    }
+
+    #[test]
+    fn run_for_cycles_only_runs_whole_instructions() {
+        // LD BC,nn then NOPs
+        let mut cpu = test_cpu(&[0x01, 0x34, 0x12]);
+        assert_eq!(cpu.run_for_cycles(4).unwrap(), 12);
+        assert_eq!(cpu.run_for_cycles(6).unwrap(), 8);
+        assert_eq!(cpu.registers.pc, 0x0005);
+    }
+
+    #[test]
+    fn run_until_checks_the_predicate_after_each_instruction() {
+        // INC A, three times
+        let mut cpu = test_cpu(&[0x3C, 0x3C, 0x3C]);
+        assert_eq!(cpu.run_until(|cpu| cpu.registers.a == 2).unwrap(), 8);
+        assert_eq!(cpu.registers.pc, 0x0002);
+    }
+
+    #[test]
+    fn run_until_frame_stops_when_the_ppu_enters_vblank() {
+        let mut cpu = test_cpu(&[]);
+        cpu.memory.write_bytes(LCDC_ADDRESS, 0x80);
+        // 144 visible lines of 456 cycles
+        assert_eq!(cpu.run_until_frame().unwrap(), 65664);
+        assert_eq!(cpu.memory.ppu.ly, 144);
+        assert!(cpu.memory.requested_interrupts() & Interrupt::VBlank.bit() != 0);
+        assert_eq!(cpu.run_until_frame().unwrap(), CYCLES_PER_FRAME);
+        assert_eq!(cpu.memory.ppu.ly, 144);
+    }
+
+    #[test]
+    fn run_until_frame_runs_a_frame_worth_of_cycles_with_the_lcd_off() {
+        let mut cpu = test_cpu(&[]);
+        assert_eq!(cpu.run_until_frame().unwrap(), CYCLES_PER_FRAME);
+        assert_eq!(cpu.run_until_frame().unwrap(), CYCLES_PER_FRAME);
+        assert_eq!(cpu.memory.ppu.ly, 0);
+    }
+
+    #[test]
+    fn pc_wraps_around_the_address_space() {
+        // NOP at 0xFFFF, which is the IE register
+        let mut cpu = test_cpu(&[0x3C]);
+        cpu.registers.pc = 0xFFFF;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, 0x0000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn relative_jumps_wrap_below_zero() {
+        // JR -3
+        let mut cpu = test_cpu(&[0x18, 0xFD]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.pc, 0xFFFF);
+    }
+
+    #[test]
+    fn sp_wraps_around_the_address_space() {
+        // POP BC with SP on IE, the high byte comes from 0x0000
+        let mut cpu = test_cpu(&[0xC1]);
+        cpu.registers.sp = 0xFFFF;
+        cpu.memory.write_bytes(IE_ADDRESS, 0x42);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.sp, 0x0001);
+        assert_eq!(cpu.registers.get_register_value_u16(Reg16::BC), 0xC142);
+
+        // PUSH BC from SP 0x0001 writes 0x0000 then IE
+        let mut cpu = test_cpu(&[0xC5]);
+        cpu.registers.sp = 0x0001;
+        cpu.registers.set_register_value_u16(Reg16::BC, 0x1234);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.sp, 0xFFFF);
+        assert_eq!(cpu.memory.read_bytes(IE_ADDRESS), 0x34);
+    }
+
+    #[test]
+    fn hl_increment_and_decrement_wrap() {
+        // LD (HL+),A ; LD A,(HL-)
+        let mut cpu = test_cpu(&[0x22, 0x3A]);
+        cpu.registers.a = 0x1F;
+        cpu.registers.set_register_value_u16(Reg16::HL, 0xFFFF);
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.read_bytes(IE_ADDRESS), 0x1F);
+        assert_eq!(cpu.registers.get_register_value_u16(Reg16::HL), 0x0000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x22);
+        assert_eq!(cpu.registers.get_register_value_u16(Reg16::HL), 0xFFFF);
+    }
+
+    #[test]
+    fn skipping_the_boot_rom_leaves_the_post_boot_state() {
+        let mut cpu = test_cpu(&[]);
+        cpu.memory.load_boot_rom(&[0x31; 0x100]);
+        cpu.memory.write_bytes(IE_ADDRESS, 0x1F);
+        cpu.skip_boot_rom(Model::Dmg);
 
-    pub fn to_string(&self) -> String {
-        return format!("CPU | {}", self.registers_to_string());
+        assert!(!cpu.memory.boot_rom_mapped());
+        assert_eq!(cpu.registers.pc, 0x0100);
+        assert_eq!(cpu.registers.sp, 0xFFFE);
+        // the header checksum of an empty rom is 0x00, Z only
+        assert_eq!(cpu.registers.get_register_value_u16(Reg16::AF), 0x0180);
+        assert_eq!(cpu.memory.read_bytes(DIV_ADDRESS), 0xAB);
+        assert_eq!(cpu.memory.read_bytes(LCDC_ADDRESS), 0x91);
+        assert_eq!(cpu.memory.read_bytes(IF_ADDRESS), 0xE1);
+        assert_eq!(cpu.memory.read_bytes(IE_ADDRESS), 0x00);
     }
 }
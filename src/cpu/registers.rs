@@ -1,70 +1,270 @@
-// #[derive(Clone)]
-// pub struct Registers {
-//     // 8 bytes registers
-//     pub a: u8,
-//     pub b: u8,
-//     pub c: u8,
-//     pub d: u8,
-//     pub e: u8,
-//     pub f: u8,
-//     pub h: u8,
-//     pub l: u8,
-//     // 16 bytes registers
-//     pub sp: usize, // Stack Pointer register points to the current stack position
-//     pub pc: usize, // Program Counter register point to the next instruction to be executed in GB memory
-// }
-
-// impl Registers {
-//     pub fn new() -> Registers {
-//         Registers {
-//             a: 0,
-//             b: 0,
-//             c: 0,
-//             d: 0,
-//             e: 0,
-//             f: 0,
-//             h: 0,
-//             l: 0,
-//             sp: 0,
-//             pc: 0,
-//         }
-//     }
-
-//     pub fn get_register_value(&mut self, register_name: &str) -> u8 {
-//         match register_name {
-//             "a" => self.a,
-//             "b" => self.b,
-//             "c" => self.c,
-//             "d" => self.d,
-//             "e" => self.e,
-//             "f" => self.f,
-//             "h" => self.h,
-//             "l" => self.l,
-//             _ => panic!("unknown cpu register name"),
-//         }
-//     }
-
-//     pub fn set_register_value(&mut self, register_name: &str, value: u8) {
-//         match register_name {
-//             "a" => self.a = value,
-//             "b" => self.b = value,
-//             "c" => self.c = value,
-//             "d" => self.d = value,
-//             "e" => self.e = value,
-//             "f" => self.f = value,
-//             "h" => self.h = value,
-//             "l" => self.l = value,
-//             _ => panic!("unknown cpu register name"),
-//         }
-//     }
-
-//     pub fn r2_to_r1(&mut self, r1: &str, r2: &str) {
-//         let r2_value = self.get_register_value(r2);
-//         self.set_register_value(r1, r2_value);
-//     }
-
-//     pub fn to_string(&self) -> String {
-//         return format!("Registers state:\n(a: {}) (b: {}) c: {}) (d: {})\n(e: {}) (f: {}) (h: {}) (l: {})\n(sp: {}) (pc: {})",
-//             self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l, self.sp, self.pc);
-//     }
-// }
+use std::fmt;
+
+// 8 bits registers, F is only reachable through Flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg8 {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+// 16 bits registers, the pairs combine two 8 bits registers high byte first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg16 {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+// branch conditions used by JP, JR, CALL and RET
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+// Flag register (f), the lower nibble always reads as zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags(u8);
+
+impl Flags {
+    const Z: u8 = 0x80; // Zero
+    const N: u8 = 0x40; // Subtract
+    const H: u8 = 0x20; // Half carry
+    const C: u8 = 0x10; // Carry
+
+    pub fn from_bits(bits: u8) -> Flags {
+        Flags(bits & 0xF0)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    fn get(self, flag: u8) -> bool {
+        self.0 & flag != 0
+    }
+
+    fn set(&mut self, flag: u8, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+
+    pub fn z(self) -> bool {
+        self.get(Flags::Z)
+    }
+
+    pub fn n(self) -> bool {
+        self.get(Flags::N)
+    }
+
+    pub fn h(self) -> bool {
+        self.get(Flags::H)
+    }
+
+    pub fn c(self) -> bool {
+        self.get(Flags::C)
+    }
+
+    pub fn set_z(&mut self, value: bool) {
+        self.set(Flags::Z, value);
+    }
+
+    pub fn set_n(&mut self, value: bool) {
+        self.set(Flags::N, value);
+    }
+
+    pub fn set_h(&mut self, value: bool) {
+        self.set(Flags::H, value);
+    }
+
+    pub fn set_c(&mut self, value: bool) {
+        self.set(Flags::C, value);
+    }
+}
+
+#[derive(Clone)]
+pub struct Registers {
+    // 8 bytes registers
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub f: Flags,
+    pub h: u8,
+    pub l: u8,
+    // 16 bytes registers
+    pub sp: u16, // Stack Pointer register points to the current stack position
+    pub pc: u16, // Program Counter register point to the next instruction to be executed in GB memory
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            f: Flags::default(),
+            h: 0,
+            l: 0,
+            sp: 0,
+            pc: 0,
+        }
+    }
+
+    pub fn get_register_value_u8(&self, register: Reg8) -> u8 {
+        match register {
+            Reg8::A => self.a,
+            Reg8::B => self.b,
+            Reg8::C => self.c,
+            Reg8::D => self.d,
+            Reg8::E => self.e,
+            Reg8::H => self.h,
+            Reg8::L => self.l,
+        }
+    }
+
+    pub fn set_register_value_u8(&mut self, register: Reg8, value: u8) {
+        match register {
+            Reg8::A => self.a = value,
+            Reg8::B => self.b = value,
+            Reg8::C => self.c = value,
+            Reg8::D => self.d = value,
+            Reg8::E => self.e = value,
+            Reg8::H => self.h = value,
+            Reg8::L => self.l = value,
+        }
+    }
+
+    pub fn get_register_value_u16(&self, register: Reg16) -> u16 {
+        match register {
+            Reg16::AF => u16::from_be_bytes([self.a, self.f.bits()]),
+            Reg16::BC => u16::from_be_bytes([self.b, self.c]),
+            Reg16::DE => u16::from_be_bytes([self.d, self.e]),
+            Reg16::HL => u16::from_be_bytes([self.h, self.l]),
+            Reg16::SP => self.sp,
+        }
+    }
+
+    pub fn set_register_value_u16(&mut self, register: Reg16, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        match register {
+            Reg16::AF => {
+                self.a = high;
+                self.f = Flags::from_bits(low);
+            }
+            Reg16::BC => {
+                self.b = high;
+                self.c = low;
+            }
+            Reg16::DE => {
+                self.d = high;
+                self.e = low;
+            }
+            Reg16::HL => {
+                self.h = high;
+                self.l = low;
+            }
+            Reg16::SP => self.sp = value,
+        }
+    }
+
+    pub fn r2_to_r1(&mut self, r1: Reg8, r2: Reg8) {
+        let r2_value = self.get_register_value_u8(r2);
+        self.set_register_value_u8(r1, r2_value);
+    }
+
+    pub fn condition(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::NZ => !self.f.z(),
+            Condition::Z => self.f.z(),
+            Condition::NC => !self.f.c(),
+            Condition::C => self.f.c(),
+        }
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers::new()
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Registers state:\n(a: {}) (b: {}) c: {}) (d: {})\n(e: {}) (f: {}) (h: {}) (l: {})\n(sp: {}) (pc: {})",
+            self.a, self.b, self.c, self.d, self.e, self.f.bits(), self.h, self.l, self.sp, self.pc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, Flags, Reg16, Reg8, Registers};
+
+    #[test]
+    fn pairs_are_high_byte_first() {
+        let mut registers = Registers::new();
+        registers.set_register_value_u16(Reg16::BC, 0x1234);
+        registers.set_register_value_u16(Reg16::DE, 0x5678);
+        registers.set_register_value_u16(Reg16::HL, 0x9ABC);
+        assert_eq!((registers.b, registers.c), (0x12, 0x34));
+        assert_eq!((registers.d, registers.e), (0x56, 0x78));
+        assert_eq!((registers.h, registers.l), (0x9A, 0xBC));
+        assert_eq!(registers.get_register_value_u16(Reg16::HL), 0x9ABC);
+    }
+
+    #[test]
+    fn low_nibble_of_f_always_reads_zero() {
+        let mut registers = Registers::new();
+        registers.set_register_value_u16(Reg16::AF, 0x12FF);
+        assert_eq!(registers.a, 0x12);
+        assert_eq!(registers.f.bits(), 0xF0);
+        assert_eq!(registers.get_register_value_u16(Reg16::AF), 0x12F0);
+        assert_eq!(Flags::from_bits(0x0F).bits(), 0x00);
+    }
+
+    #[test]
+    fn flag_accessors_map_to_their_bits() {
+        let mut flags = Flags::default();
+        flags.set_z(true);
+        flags.set_c(true);
+        assert_eq!(flags.bits(), 0x90);
+        flags.set_n(true);
+        flags.set_h(true);
+        flags.set_z(false);
+        assert_eq!(flags.bits(), 0x70);
+        assert!(flags.n() && flags.h() && flags.c() && !flags.z());
+    }
+
+    #[test]
+    fn conditions_follow_z_and_c() {
+        let mut registers = Registers::new();
+        registers.f.set_z(true);
+        assert!(registers.condition(Condition::Z));
+        assert!(!registers.condition(Condition::NZ));
+        assert!(registers.condition(Condition::NC));
+        assert!(!registers.condition(Condition::C));
+    }
+
+    #[test]
+    fn r2_to_r1_copies_between_8_bits_registers() {
+        let mut registers = Registers::new();
+        registers.set_register_value_u8(Reg8::E, 0x42);
+        registers.r2_to_r1(Reg8::A, Reg8::E);
+        assert_eq!(registers.get_register_value_u8(Reg8::A), 0x42);
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::CPU;
+use crate::bus::Bus;
+
+enum Output {
+    Writer {
+        writer: BufWriter<Box<dyn Write + Send>>,
+        // the first write error, tracing stops after it
+        error: Option<io::Error>,
+    },
+    // lines kept until taken, used to compare a run against a reference trace
+    Lines(Vec<String>),
+}
+
+// writes one line per executed instruction in the Gameboy Doctor format
+// (https://github.com/robert/gameboy-doctor):
+// A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+// the state is logged before the instruction runs, interrupt dispatches are not logged
+// clones of a cpu share the same output
+#[derive(Clone)]
+pub struct Tracer {
+    output: Arc<Mutex<Output>>,
+}
+
+impl Tracer {
+    pub fn new(writer: Box<dyn Write + Send>) -> Tracer {
+        Tracer::with_output(Output::Writer {
+            writer: BufWriter::new(writer),
+            error: None,
+        })
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+        Ok(Tracer::new(Box::new(File::create(path)?)))
+    }
+
+    // keeps the lines in memory, they are retrieved with `take_lines`
+    pub fn in_memory() -> Tracer {
+        Tracer::with_output(Output::Lines(Vec::new()))
+    }
+
+    fn with_output(output: Output) -> Tracer {
+        Tracer {
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+
+    pub fn trace<B: Bus>(&self, cpu: &CPU<B>) {
+        match &mut *self.output.lock().unwrap() {
+            Output::Writer { error: Some(_), .. } => {}
+            Output::Writer { writer, error } => {
+                if let Err(write_error) = writeln!(writer, "{}", doctor_line(cpu)) {
+                    *error = Some(write_error);
+                }
+            }
+            Output::Lines(lines) => lines.push(doctor_line(cpu)),
+        }
+    }
+
+    // flushes the trace, returns the error that stopped tracing if any
+    pub fn flush(&self) -> io::Result<()> {
+        match &mut *self.output.lock().unwrap() {
+            Output::Writer { writer, error } => match error.take() {
+                Some(error) => Err(error),
+                None => writer.flush(),
+            },
+            Output::Lines(_) => Ok(()),
+        }
+    }
+
+    // lines traced since the last call, always empty when writing to a file
+    pub fn take_lines(&self) -> Vec<String> {
+        match &mut *self.output.lock().unwrap() {
+            Output::Lines(lines) => std::mem::take(lines),
+            Output::Writer { .. } => Vec::new(),
+        }
+    }
+}
+
+pub fn doctor_line<B: Bus>(cpu: &CPU<B>) -> String {
+    let registers = &cpu.registers;
+    let pc = registers.pc;
+    let pcmem: Vec<String> = (0..4)
+        .map(|offset| format!("{:02X}", cpu.memory.read(pc.wrapping_add(offset))))
+        .collect();
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{}",
+        registers.a,
+        registers.f.bits(),
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        pc,
+        pcmem.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{test_cpu, Reg16};
+
+    #[test]
+    fn doctor_line_shows_registers_and_the_next_bytes() {
+        // LD BC, $1234 ; NOP
+        let mut cpu = test_cpu(&[0x01, 0x34, 0x12, 0x00]);
+        cpu.registers.set_register_value_u16(Reg16::AF, 0x01B0);
+        cpu.registers.set_register_value_u16(Reg16::HL, 0x014D);
+        assert_eq!(
+            doctor_line(&cpu),
+            "A:01 F:B0 B:00 C:00 D:00 E:00 H:01 L:4D SP:FFFE PC:0000 PCMEM:01,34,12,00"
+        );
+    }
+
+    #[test]
+    fn every_instruction_is_traced_before_it_runs() {
+        // LD BC, $1234 ; INC A
+        let mut cpu = test_cpu(&[0x01, 0x34, 0x12, 0x3C]);
+        let tracer = Tracer::in_memory();
+        cpu.tracer = Some(tracer.clone());
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        let lines = tracer.take_lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("PC:0000 PCMEM:01,34,12,3C"));
+        assert!(lines[1].starts_with("A:00 F:00 B:12 C:34"));
+        assert!(lines[1].contains("PC:0003"));
+        assert!(tracer.take_lines().is_empty());
+    }
+
+    #[test]
+    fn doctor_mode_stubs_ly() {
+        // LDH A, ($44)
+        let mut cpu = test_cpu(&[0xF0, 0x44]);
+        cpu.memory.ppu.ly = 0x12;
+        cpu.memory.doctor = true;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x90);
+    }
+}
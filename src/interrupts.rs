@@ -0,0 +1,45 @@
+// Interrupt requests (IF) and interrupt enable (IE) registers
+pub const IF_ADDRESS: usize = 0xFF0F;
+pub const IE_ADDRESS: usize = 0xFFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl Interrupt {
+    // ordered by priority, VBlank is serviced first
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::Stat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    // bit used by the interrupt in both IF and IE
+    pub fn bit(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0x01,
+            Interrupt::Stat => 0x02,
+            Interrupt::Timer => 0x04,
+            Interrupt::Serial => 0x08,
+            Interrupt::Joypad => 0x10,
+        }
+    }
+
+    // address the cpu jumps to when servicing the interrupt
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::VBlank => 0x40,
+            Interrupt::Stat => 0x48,
+            Interrupt::Timer => 0x50,
+            Interrupt::Serial => 0x58,
+            Interrupt::Joypad => 0x60,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+pub mod bootrom;
+pub mod bus;
+pub mod cpu;
+pub mod interrupts;
+pub mod memory;
+pub mod model;
+pub mod timer;
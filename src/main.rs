@@ -1,77 +1,244 @@
-mod bootrom;
-// mod instruction;
-mod cpu;
-mod memory;
-
+use std::env;
 use std::fs;
+use std::io::BufReader;
+use std::process;
+
+use my_bg_rust::cpu;
+use my_bg_rust::cpu::coverage;
+use my_bg_rust::cpu::divergence;
+use my_bg_rust::cpu::{History, Tracer};
+use my_bg_rust::memory::Header;
+use my_bg_rust::model::Model;
+
+// instructions shown before and after a divergence
+const DIVERGENCE_CONTEXT: usize = 5;
+
+// command line of the emulator:
+// [--boot-rom <file>] [--model <model>] [--trace <file>] [--history <count>] [rom]
+struct Options {
+    // without a boot rom the cpu starts at 0x0100 in the state the boot rom leaves
+    boot_rom: Option<String>,
+    model: Model,
+    trace: Option<String>,
+    history: usize,
+    rom: Option<String>,
+}
 
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        boot_rom: None,
+        model: Model::Dmg,
+        trace: None,
+        history: 0,
+        rom: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--boot-rom" => {
+                let path = args.next().ok_or("--boot-rom expects a file")?;
+                options.boot_rom = Some(path.clone());
+            }
+            "--model" => {
+                let name = args.next().ok_or("--model expects a model name")?;
+                options.model = name.parse()?;
+            }
+            // Gameboy Doctor log of every executed instruction
+            "--trace" => {
+                let path = args.next().ok_or("--trace expects a file")?;
+                options.trace = Some(path.clone());
+            }
+            // instructions kept to be shown when the cpu fails
+            "--history" => {
+                let count = args.next().ok_or("--history expects a count")?;
+                options.history = count
+                    .parse()
+                    .map_err(|_| format!("invalid history count {}", count))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => options.rom = Some(arg.clone()),
+        }
+    }
 
-// will parse the rom file to a list of cpu operation to execute
-fn parse_rom(rom: &str) -> Vec<String> {
-    let v: Vec<String> = rom.lines().map(str::to_string).collect();
-    for line in &v{
-        println!("{}", line);
+    if options.boot_rom.is_none() && options.rom.is_none() {
+        return Err(String::from("nothing to run, give a rom or a --boot-rom"));
     }
+    Ok(options)
+}
 
-    return v;
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        // lists implemented, missing and mismatched opcodes
+        Some("coverage") => {
+            let report = coverage::coverage_report();
+            print!("{}", report);
+            if !report.is_complete() {
+                process::exit(1);
+            }
+        }
+        // decodes and checks the cartridge header of a rom
+        Some("header") => {
+            let rom = args.get(2).unwrap_or_else(|| {
+                eprintln!("usage: header <rom>");
+                process::exit(2);
+            });
+            let bytes = fs::read(rom).expect("Could not read rom file");
+            match Header::parse(&bytes) {
+                Ok(header) => {
+                    print!("{}", header);
+                    if !header.is_valid() {
+                        process::exit(1);
+                    }
+                }
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+            }
+        }
+        Some("diverge") => {
+            let reference = args.get(2).unwrap_or_else(|| {
+                eprintln!("usage: diverge <reference trace> [options] [rom]");
+                process::exit(2);
+            });
+            match parse_options(&args[3..]) {
+                Ok(options) => diverge(reference, options),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(2);
+                }
+            }
+        }
+        _ => match parse_options(&args[1..]) {
+            Ok(options) => run(options),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(2);
+            }
+        },
+    }
 }
 
+fn create_cpu(options: &Options) -> cpu::CPU {
+    let mut cpu = cpu::CPU::new();
+    cpu.memory.model = options.model;
+    if let Some(rom) = &options.rom {
+        let bytes = fs::read(rom).expect("Could not read rom file");
+        if let Err(error) = cpu.memory.load_cartridge(&bytes) {
+            eprintln!("{}: {}", rom, error);
+            process::exit(1);
+        }
+    }
 
-// fn read_rom_file(file_path: &str) -> &[u8] {
-//     let contents = fs::read_to_string(file_path)
-//         .expect("Could not read rom file");
+    match &options.boot_rom {
+        // the boot rom runs from 0x0000 and hands over to the cartridge at 0x0100
+        Some(path) => {
+            let bytes = fs::read(path).expect("Could not read boot rom file");
+            cpu.memory.load_boot_rom(&bytes);
+        }
+        None => cpu.skip_boot_rom(options.model),
+    }
 
-//     return contents.as_bytes();
-// }
+    if options.history > 0 {
+        cpu.history = Some(History::new(options.history));
+    }
+    cpu
+}
 
-fn main() {
-    // basic emulator loop
-    // while (¡stop_emulation)
-    // {
-    // executeCPU(cycles_to_execute);
-    // generateInterrupts();
-    // emulateGraphics();
-    // emulateSound();
-    // emulateOtherSoftware();
-    // timeSincronization();
-    // }
-    // println!("{}", bootrom::BOOT_ROM_INSTRUCTIONS);
-    // parse_rom(bootrom::BOOT_ROM_INSTRUCTIONS);
-
-    // let i = instruction::Instruction::new(0x06, 8, 1, "LD B, n");
-    // println!("{}", i._str);
+// runs the rom against a Gameboy Doctor reference log and shows where they differ
+fn diverge(reference: &str, options: Options) {
+    let file = fs::File::open(reference).expect("Could not read reference trace");
+    let mut cpu = create_cpu(&options);
+    match divergence::find_divergence(&mut cpu, BufReader::new(file), DIVERGENCE_CONTEXT) {
+        Ok(None) => println!("no divergence, the whole reference trace matched"),
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            print_history(&cpu);
+            process::exit(1);
+        }
+    }
+}
 
-    let mut cpu = cpu::CPU::new();
-    // println!("{}", cpu.to_string());
-    // (cpu.instruction_set.get(&0x06).unwrap().execute)(&mut cpu, &vec![0x08]);
-    // println!("{}", cpu.to_string());
+// shows the instructions that led to an error, oldest first
+fn print_history(cpu: &cpu::CPU) {
+    if let Some(history) = &cpu.history {
+        eprintln!("last {} executed instructions:", history.len());
+        eprint!("{}", history);
+    }
+}
 
+fn run(options: Options) {
+    let mut cpu = create_cpu(&options);
+    if let Some(path) = &options.trace {
+        let tracer = Tracer::create(path).expect("Could not create trace file");
+        cpu.tracer = Some(tracer);
+        // traces are compared against Gameboy Doctor logs
+        cpu.memory.doctor = true;
+    }
 
-    
-    // Iterate over everything.
-    // for (op_code, instruction) in cpu.instruction_set {
-    //     println!("{op_code}: \"{}\"", instruction._str);
-    // }
+    loop {
+        let result = cpu.run_until_frame();
+        // the trace is flushed every frame so it stays usable when the emulator is killed
+        if let Some(tracer) = &cpu.tracer {
+            if let Err(error) = tracer.flush() {
+                eprintln!("Could not write trace: {}", error);
+                process::exit(1);
+            }
+        }
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            eprintln!("{}", cpu);
+            print_history(&cpu);
+            process::exit(1);
+        }
+    }
+}
 
-    let bytes = fs::read("/home/bourgh_s/my_gb_rust/tests/assets/dmg_boot.bin")
-        .expect("Could not read rom file");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    cpu.memory.load_rom(&bytes);
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
 
-    // println!("{}", cpu.memory.to_string());
+    #[test]
+    fn the_boot_rom_is_optional() {
+        let options = parse_options(&args(&["--boot-rom", "dmg_boot.bin", "game.gb"])).unwrap();
+        assert_eq!(options.boot_rom.as_deref(), Some("dmg_boot.bin"));
+        assert_eq!(options.rom.as_deref(), Some("game.gb"));
 
-    // let bytes = contents.as_bytes();
+        let options = parse_options(&args(&["game.gb"])).unwrap();
+        assert_eq!(options.boot_rom, None);
 
-    // let mut counter = 0;
-    loop {
-        // if counter > 10 {
-        //     break;
-        // }
-        // println!("{}", bytes[counter]);
-        cpu.execute_instruction();
-        // &bytes[counter], &[].to_vec());
-        println!("{}", cpu.to_string());
-        // counter+=1;
+        // the boot rom alone runs with an empty cartridge slot
+        assert!(parse_options(&args(&["--boot-rom", "dmg_boot.bin"])).is_ok());
+        assert!(parse_options(&args(&[])).is_err());
+        assert!(parse_options(&args(&["--boot-rom"])).is_err());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn options_are_parsed() {
+        let options = parse_options(&args(&[
+            "--model",
+            "cgb",
+            "--trace",
+            "out.log",
+            "--history",
+            "16",
+            "game.gb",
+        ]))
+        .unwrap();
+        assert_eq!(options.model, Model::Cgb);
+        assert_eq!(options.trace.as_deref(), Some("out.log"));
+        assert_eq!(options.history, 16);
+        assert!(parse_options(&args(&["--history", "many", "game.gb"])).is_err());
+        assert!(parse_options(&args(&["--fast", "game.gb"])).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+use super::header::{Header, HeaderError};
+
+// size of one switchable rom bank, 0x4000-0x7FFF
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+// chip on the cartridge that switches banks when the rom area is written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mapper {
+    // 32KB of rom, writes to the rom area are ignored
+    None,
+    // up to 2MB of rom and 32KB of ram
+    Mbc1,
+    // up to 256KB of rom and 512 half bytes of ram built in
+    Mbc2,
+    // up to 2MB of rom and 32KB of ram, the real time clock is not emulated
+    Mbc3,
+    // up to 8MB of rom and 128KB of ram
+    Mbc5,
+    // the ones below are recognized in the header but not emulated
+    Mbc6,
+    Mbc7,
+    Mmm01,
+    PocketCamera,
+    Tama5,
+    Huc1,
+    Huc3,
+}
+
+impl Mapper {
+    // largest rom the bank registers can address, None for the mappers not emulated
+    pub fn max_rom_size(self) -> Option<usize> {
+        match self {
+            Mapper::None => Some(2 * ROM_BANK_SIZE),
+            Mapper::Mbc1 | Mapper::Mbc3 => Some(128 * ROM_BANK_SIZE),
+            Mapper::Mbc2 => Some(16 * ROM_BANK_SIZE),
+            Mapper::Mbc5 => Some(512 * ROM_BANK_SIZE),
+            _ => None,
+        }
+    }
+}
+
+// rom (0x0000-0x7FFF) and external ram (0xA000-0xBFFF)
+#[derive(Clone)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    pub mapper: Mapper,
+    // bank registers, their width depends on the mapper:
+    // `rom_bank` holds the 5 low bits of the rom bank on MBC1, the whole bank otherwise,
+    // `ram_bank` holds the 2 bits shared by the upper rom bank bits and the ram bank on MBC1,
+    // the ram bank (or real time clock register from 0x08) on MBC3 and MBC5
+    rom_bank: usize,
+    ram_bank: usize,
+    // MBC1 banking mode, when set `ram_bank` also applies to 0x0000-0x3FFF and the ram
+    mode: bool,
+    ram_enabled: bool,
+}
+
+impl Cartridge {
+    // raw image without a meaningful header, e.g. a test program, with 8KB of ram
+    pub fn new(rom: Vec<u8>) -> Cartridge {
+        Cartridge::with_mapper(rom, Mapper::None, RAM_BANK_SIZE)
+    }
+
+    // picks the mapper and ram size announced by the header, rejecting bad dumps
+    // and roms the mapper cannot address
+    pub fn from_rom(rom: Vec<u8>) -> Result<(Cartridge, Header), HeaderError> {
+        let header = Header::validate(&rom)?;
+        let mapper = header.cartridge_type.mapper;
+        let max = mapper
+            .max_rom_size()
+            .ok_or(HeaderError::UnsupportedMapper(mapper))?;
+        if header.rom_size() > max {
+            return Err(HeaderError::RomTooLarge {
+                mapper,
+                size: header.rom_size(),
+                max,
+            });
+        }
+        let cartridge = Cartridge::with_mapper(rom, mapper, header.ram_size());
+        Ok((cartridge, header))
+    }
+
+    fn with_mapper(rom: Vec<u8>, mapper: Mapper, ram_size: usize) -> Cartridge {
+        Cartridge {
+            rom,
+            ram: vec![0; ram_size],
+            mapper,
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: false,
+            ram_enabled: mapper == Mapper::None,
+        }
+    }
+
+    // the rom itself, e.g. to patch the boot rom over its start
+    pub fn rom_mut(&mut self) -> &mut [u8] {
+        &mut self.rom
+    }
+
+    fn rom_bank_count(&self) -> usize {
+        self.rom.len().div_ceil(ROM_BANK_SIZE).max(1)
+    }
+
+    // rom bank mapped at the given address of 0x0000-0x7FFF
+    pub fn rom_bank(&self, pointer: usize) -> usize {
+        let bank = match (self.mapper, pointer) {
+            (Mapper::Mbc1, 0x0000..=0x3FFF) if self.mode => self.ram_bank << 5,
+            (_, 0x0000..=0x3FFF) => 0,
+            (Mapper::Mbc1, _) => self.ram_bank << 5 | self.rom_bank,
+            (_, _) => self.rom_bank,
+        };
+        match self.mapper {
+            // without a mapper the image is wired as is, a short one leaves the end open
+            Mapper::None => bank,
+            // the bank lines beyond the size of the rom are not connected
+            _ => bank % self.rom_bank_count(),
+        }
+    }
+
+    pub fn read_rom(&self, pointer: usize) -> u8 {
+        let offset = self.rom_bank(pointer) * ROM_BANK_SIZE + pointer % ROM_BANK_SIZE;
+        // reads past the end of the rom see an open bus
+        self.rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    // writes to the rom area go to the mapper registers
+    pub fn write_rom(&mut self, pointer: usize, data: u8) {
+        match (self.mapper, pointer) {
+            (Mapper::Mbc1 | Mapper::Mbc3 | Mapper::Mbc5, 0x0000..=0x1FFF) => {
+                self.ram_enabled = data & 0x0F == 0x0A
+            }
+            // bank 0 cannot be mapped at 0x4000, selecting it maps bank 1
+            (Mapper::Mbc1, 0x2000..=0x3FFF) => self.rom_bank = (data & 0x1F).max(1) as usize,
+            (Mapper::Mbc1, 0x4000..=0x5FFF) => self.ram_bank = (data & 0x03) as usize,
+            (Mapper::Mbc1, 0x6000..=0x7FFF) => self.mode = data & 0x01 != 0,
+            // bit 8 of the address tells the ram enable and rom bank registers apart
+            (Mapper::Mbc2, 0x0000..=0x3FFF) if pointer & 0x100 == 0 => {
+                self.ram_enabled = data & 0x0F == 0x0A
+            }
+            (Mapper::Mbc2, 0x0000..=0x3FFF) => self.rom_bank = (data & 0x0F).max(1) as usize,
+            (Mapper::Mbc3, 0x2000..=0x3FFF) => self.rom_bank = (data & 0x7F).max(1) as usize,
+            (Mapper::Mbc3, 0x4000..=0x5FFF) => self.ram_bank = data as usize,
+            // unlike the others, MBC5 can map bank 0 at 0x4000
+            (Mapper::Mbc5, 0x2000..=0x2FFF) => {
+                self.rom_bank = (self.rom_bank & 0x100) | data as usize
+            }
+            (Mapper::Mbc5, 0x3000..=0x3FFF) => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((data & 0x01) as usize) << 8
+            }
+            (Mapper::Mbc5, 0x4000..=0x5FFF) => self.ram_bank = (data & 0x0F) as usize,
+            // MBC3 clock latch, and the registers of mappers not emulated
+            _ => {}
+        }
+    }
+
+    // offset in `ram` of an address of 0xA000-0xBFFF, None when nothing answers
+    fn ram_offset(&self, pointer: usize) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = match self.mapper {
+            Mapper::Mbc1 if self.mode => self.ram_bank,
+            // the real time clock registers are not emulated
+            Mapper::Mbc3 if self.ram_bank >= 0x08 => return None,
+            Mapper::Mbc3 | Mapper::Mbc5 => self.ram_bank,
+            _ => 0,
+        };
+        // smaller rams are mirrored over the whole area
+        Some((bank * RAM_BANK_SIZE + pointer - 0xA000) % self.ram.len())
+    }
+
+    pub fn read_ram(&self, pointer: usize) -> u8 {
+        // without ram on the cartridge the bus is left floating
+        match self.ram_offset(pointer) {
+            // MBC2 ram is 4 bits wide, the upper ones are floating
+            Some(offset) if self.mapper == Mapper::Mbc2 => self.ram[offset] | 0xF0,
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, pointer: usize, data: u8) {
+        if let Some(offset) = self.ram_offset(pointer) {
+            self.ram[offset] = data;
+        }
+    }
+}
+
+// no cartridge inserted, the rom area reads as zeros
+impl Default for Cartridge {
+    fn default() -> Self {
+        Cartridge::new(vec![0; 2 * ROM_BANK_SIZE])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::header::test_rom;
+
+    // every bank starts with its own number, e.g. 0x4000 holds 0x01
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        number_banks(&mut rom);
+        rom
+    }
+
+    // the first two bytes of every bank hold its number, outside of the header
+    fn number_banks(rom: &mut [u8]) {
+        for (bank, data) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            data[0] = bank as u8;
+            data[1] = (bank >> 8) as u8;
+        }
+    }
+
+    fn bank_at(cartridge: &Cartridge, pointer: usize) -> usize {
+        let low = cartridge.read_rom(pointer) as usize;
+        let high = cartridge.read_rom(pointer + 1) as usize;
+        high << 8 | low
+    }
+
+    fn header_rom(cartridge_type: u8, rom_size_code: u8) -> Vec<u8> {
+        let mut rom = test_rom(cartridge_type, rom_size_code, 0x00);
+        number_banks(&mut rom);
+        rom
+    }
+
+    #[test]
+    fn roms_without_mapper_ignore_writes() {
+        let mut cartridge = Cartridge::new(numbered_rom(2));
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        // reads past the end of a short image see an open bus
+        let cartridge = Cartridge::new(vec![0; 0x100]);
+        assert_eq!(cartridge.read_rom(0x4000), 0xFF);
+    }
+
+    #[test]
+    fn mbc1_bank_0_maps_bank_1() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(32), Mapper::Mbc1, 0);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        cartridge.write_rom(0x2000, 0x05);
+        assert_eq!(bank_at(&cartridge, 0x4000), 5);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0);
+        cartridge.write_rom(0x3FFF, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        // only 5 bits are kept, 0x20 is seen as 0 and maps bank 1
+        cartridge.write_rom(0x2000, 0x20);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+    }
+
+    #[test]
+    fn mbc1_upper_bits_select_the_bank_above_0x1f() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(128), Mapper::Mbc1, 0);
+        cartridge.write_rom(0x2000, 0x03);
+        cartridge.write_rom(0x4000, 0x02);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x43);
+        assert_eq!(cartridge.rom_bank(0x4000), 0x43);
+        // banks 0x20, 0x40 and 0x60 cannot be mapped at 0x4000
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x41);
+
+        // in mode 1 the upper bits also apply to 0x0000-0x3FFF
+        assert_eq!(bank_at(&cartridge, 0x0000), 0);
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0x40);
+        assert_eq!(cartridge.rom_bank(0x0000), 0x40);
+        cartridge.write_rom(0x6000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0);
+    }
+
+    #[test]
+    fn mbc1_banks_wrap_around_small_roms() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(4), Mapper::Mbc1, 0);
+        cartridge.write_rom(0x2000, 0x06);
+        assert_eq!(bank_at(&cartridge, 0x4000), 2);
+    }
+
+    #[test]
+    fn mbc1_ram_needs_to_be_enabled() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(4), Mapper::Mbc1, RAM_BANK_SIZE);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+
+        // any value without 0xA in the low nibble disables it
+        cartridge.write_rom(0x1FFF, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+        cartridge.write_rom(0x0000, 0xFA);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+    }
+
+    #[test]
+    fn mbc1_ram_banks_are_switched_in_mode_1() {
+        let mut cartridge =
+            Cartridge::with_mapper(numbered_rom(4), Mapper::Mbc1, 4 * RAM_BANK_SIZE);
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x10);
+
+        // in mode 0 bank 0 stays mapped whatever the register holds
+        cartridge.write_rom(0x4000, 0x02);
+        assert_eq!(cartridge.read_ram(0xA000), 0x10);
+
+        cartridge.write_rom(0x6000, 0x01);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+        cartridge.write_ram(0xA000, 0x12);
+        cartridge.write_rom(0x4000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0x10);
+        cartridge.write_rom(0x4000, 0x02);
+        assert_eq!(cartridge.read_ram(0xA000), 0x12);
+    }
+
+    #[test]
+    fn mbc2_registers_are_told_apart_by_address_bit_8() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(16), Mapper::Mbc2, 0x200);
+        // bit 8 set, rom bank
+        cartridge.write_rom(0x2100, 0x0B);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x0B);
+        cartridge.write_rom(0x0100, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+
+        // bit 8 clear, ram enable
+        cartridge.write_rom(0x2000, 0x0A);
+        cartridge.write_ram(0xA000, 0x35);
+        // 4 bits wide and mirrored every 512 bytes
+        assert_eq!(cartridge.read_ram(0xA000), 0xF5);
+        assert_eq!(cartridge.read_ram(0xA200), 0xF5);
+    }
+
+    #[test]
+    fn mbc3_uses_7_bits_rom_banks_and_4_ram_banks() {
+        let mut cartridge =
+            Cartridge::with_mapper(numbered_rom(128), Mapper::Mbc3, 4 * RAM_BANK_SIZE);
+        cartridge.write_rom(0x2000, 0x45);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x45);
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_rom(0x4000, 0x03);
+        cartridge.write_ram(0xA000, 0x33);
+        cartridge.write_rom(0x4000, 0x00);
+        assert_eq!(cartridge.read_ram(0xA000), 0x00);
+        cartridge.write_rom(0x4000, 0x03);
+        assert_eq!(cartridge.read_ram(0xA000), 0x33);
+
+        // the clock registers are not emulated
+        cartridge.write_rom(0x4000, 0x08);
+        cartridge.write_ram(0xA000, 0x12);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_uses_9_bits_rom_banks_and_can_map_bank_0() {
+        let mut cartridge = Cartridge::with_mapper(numbered_rom(512), Mapper::Mbc5, 0);
+        cartridge.write_rom(0x2000, 0x23);
+        cartridge.write_rom(0x3000, 0x01);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x123);
+        assert_eq!(cartridge.rom_bank(0x4000), 0x123);
+        cartridge.write_rom(0x3000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x23);
+        cartridge.write_rom(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0);
+    }
+
+    #[test]
+    fn the_mapper_comes_from_the_header() {
+        let (cartridge, header) = Cartridge::from_rom(header_rom(0x01, 0x01)).unwrap();
+        assert_eq!(cartridge.mapper, Mapper::Mbc1);
+        assert_eq!(header.rom_size(), 4 * ROM_BANK_SIZE);
+
+        let (cartridge, _) = Cartridge::from_rom(header_rom(0x1B, 0x00)).unwrap();
+        assert_eq!(cartridge.mapper, Mapper::Mbc5);
+
+        assert_eq!(
+            Cartridge::from_rom(header_rom(0xFF, 0x00)).err(),
+            Some(HeaderError::UnsupportedMapper(Mapper::Huc1))
+        );
+    }
+
+    #[test]
+    fn roms_larger_than_the_mapper_can_address_are_rejected() {
+        // 4MB is beyond the 7 bank bits of the MBC1
+        assert_eq!(
+            Cartridge::from_rom(header_rom(0x01, 0x07)).err(),
+            Some(HeaderError::RomTooLarge {
+                mapper: Mapper::Mbc1,
+                size: 0x400000,
+                max: 0x200000,
+            })
+        );
+        // 512KB is beyond the 4 bank bits of the MBC2
+        assert!(matches!(
+            Cartridge::from_rom(header_rom(0x05, 0x04)),
+            Err(HeaderError::RomTooLarge { .. })
+        ));
+        assert!(Cartridge::from_rom(header_rom(0x01, 0x06)).is_ok());
+    }
+}
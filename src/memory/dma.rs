@@ -0,0 +1,27 @@
+// OAM DMA register, writing XX copies XX00-XX9F to OAM
+pub const DMA_ADDRESS: usize = 0xFF46;
+pub const OAM_SIZE: usize = 0xA0;
+
+// transfer started by a write to 0xFF46, one byte is copied every M-cycle
+// after a one M-cycle startup delay
+#[derive(Clone, Copy, Debug)]
+pub struct Dma {
+    pub source: u16,
+    // next byte to copy, None until the startup delay starts
+    pub index: Option<usize>,
+}
+
+impl Dma {
+    pub fn new(data: u8) -> Dma {
+        Dma {
+            source: (data as u16) << 8,
+            index: None,
+        }
+    }
+
+    // OAM stays accessible during the startup delay,
+    // it is only locked once the first byte is copied
+    pub fn is_copying(&self) -> bool {
+        self.index.is_some_and(|index| index > 0)
+    }
+}
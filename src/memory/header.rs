@@ -0,0 +1,545 @@
+use std::error::Error;
+use std::fmt;
+
+use super::cartridge::Mapper;
+use super::RAM_BANK_SIZE;
+use super::ROM_BANK_SIZE;
+
+// the header lives at 0x0100-0x014F, right after the entry point
+pub const HEADER_END: usize = 0x0150;
+pub const LOGO_ADDRESS: usize = 0x0104;
+const TITLE_ADDRESS: usize = 0x0134;
+const MANUFACTURER_CODE_ADDRESS: usize = 0x013F;
+const CGB_FLAG_ADDRESS: usize = 0x0143;
+const NEW_LICENSEE_ADDRESS: usize = 0x0144;
+const SGB_FLAG_ADDRESS: usize = 0x0146;
+const CARTRIDGE_TYPE_ADDRESS: usize = 0x0147;
+const ROM_SIZE_ADDRESS: usize = 0x0148;
+const RAM_SIZE_ADDRESS: usize = 0x0149;
+const OLD_LICENSEE_ADDRESS: usize = 0x014B;
+const VERSION_ADDRESS: usize = 0x014C;
+pub const HEADER_CHECKSUM_ADDRESS: usize = 0x014D;
+const GLOBAL_CHECKSUM_ADDRESS: usize = 0x014E;
+
+// old licensee value telling that the new licensee code is used instead
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+// compared byte for byte by the boot rom, which locks up on a mismatch
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    // plain DMG game
+    None,
+    // runs on both, with colors on a CGB (0x80)
+    Compatible,
+    // refuses to run on a DMG (0xC0)
+    Only,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Licensee {
+    // single byte code at 0x014B
+    Old(u8),
+    // two ASCII characters at 0x0144-0x0145, used when 0x014B is 0x33
+    New(String),
+}
+
+// hardware found on the cartridge, decoded from the type byte at 0x0147
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<CartridgeType> {
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (Mapper::None, false, false, false, false),
+            0x01 => (Mapper::Mbc1, false, false, false, false),
+            0x02 => (Mapper::Mbc1, true, false, false, false),
+            0x03 => (Mapper::Mbc1, true, true, false, false),
+            0x05 => (Mapper::Mbc2, false, false, false, false),
+            0x06 => (Mapper::Mbc2, false, true, false, false),
+            0x08 => (Mapper::None, true, false, false, false),
+            0x09 => (Mapper::None, true, true, false, false),
+            0x0B => (Mapper::Mmm01, false, false, false, false),
+            0x0C => (Mapper::Mmm01, true, false, false, false),
+            0x0D => (Mapper::Mmm01, true, true, false, false),
+            0x0F => (Mapper::Mbc3, false, true, true, false),
+            0x10 => (Mapper::Mbc3, true, true, true, false),
+            0x11 => (Mapper::Mbc3, false, false, false, false),
+            0x12 => (Mapper::Mbc3, true, false, false, false),
+            0x13 => (Mapper::Mbc3, true, true, false, false),
+            0x19 => (Mapper::Mbc5, false, false, false, false),
+            0x1A => (Mapper::Mbc5, true, false, false, false),
+            0x1B => (Mapper::Mbc5, true, true, false, false),
+            0x1C => (Mapper::Mbc5, false, false, false, true),
+            0x1D => (Mapper::Mbc5, true, false, false, true),
+            0x1E => (Mapper::Mbc5, true, true, false, true),
+            0x20 => (Mapper::Mbc6, false, false, false, false),
+            0x22 => (Mapper::Mbc7, true, true, false, true),
+            0xFC => (Mapper::PocketCamera, true, false, false, false),
+            0xFD => (Mapper::Tama5, false, false, false, false),
+            0xFE => (Mapper::Huc3, false, false, false, false),
+            0xFF => (Mapper::Huc1, true, true, false, false),
+            _ => return None,
+        };
+        Some(CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+impl fmt::Display for CartridgeType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mapper {
+            Mapper::None => write!(f, "ROM")?,
+            mapper => write!(f, "{:?}", mapper)?,
+        }
+        for (present, name) in [
+            (self.ram, "RAM"),
+            (self.battery, "BATTERY"),
+            (self.timer, "TIMER"),
+            (self.rumble, "RUMBLE"),
+        ] {
+            if present {
+                write!(f, "+{}", name)?;
+            }
+        }
+        write!(f, " ({:#04X})", self.code)
+    }
+}
+
+// a header that could not be decoded, or that real hardware would reject
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    // the rom ends before 0x0150
+    TooSmall {
+        len: usize,
+    },
+    UnknownCartridgeType(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    // the boot rom locks up on both of these
+    BadLogo,
+    BadHeaderChecksum {
+        expected: u8,
+        computed: u8,
+    },
+    // the header announces more banks than the dump contains
+    Truncated {
+        expected: usize,
+        len: usize,
+    },
+    // the mapper is recognized but not emulated
+    UnsupportedMapper(Mapper),
+    // the header announces more rom than the bank registers of the mapper can address
+    RomTooLarge {
+        mapper: Mapper,
+        size: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::TooSmall { len } => write!(
+                f,
+                "rom is {} bytes long, too small to hold a header ending at {:#06X}",
+                len, HEADER_END
+            ),
+            HeaderError::UnknownCartridgeType(code) => {
+                write!(f, "unknown cartridge type {:#04X}", code)
+            }
+            HeaderError::UnknownRomSize(code) => write!(f, "unknown rom size code {:#04X}", code),
+            HeaderError::UnknownRamSize(code) => write!(f, "unknown ram size code {:#04X}", code),
+            HeaderError::BadLogo => write!(f, "nintendo logo does not match"),
+            HeaderError::BadHeaderChecksum { expected, computed } => write!(
+                f,
+                "header checksum is {:#04X}, computed {:#04X}",
+                expected, computed
+            ),
+            HeaderError::Truncated { expected, len } => write!(
+                f,
+                "header announces {} bytes of rom but the dump is {} bytes long",
+                expected, len
+            ),
+            HeaderError::UnsupportedMapper(mapper) => {
+                write!(f, "{:?} cartridges are not supported yet", mapper)
+            }
+            HeaderError::RomTooLarge { mapper, size, max } => write!(
+                f,
+                "header announces {}KB of rom but {:?} addresses at most {}KB",
+                size / 1024,
+                mapper,
+                max / 1024
+            ),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+// decoded cartridge header along with the checks a dump should pass
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub title: String,
+    // four characters on later CGB games
+    pub manufacturer_code: Option<String>,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub licensee: Licensee,
+    pub cartridge_type: CartridgeType,
+    pub rom_size_code: u8,
+    pub ram_size_code: u8,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    // checks against the actual rom content
+    pub logo_valid: bool,
+    pub computed_header_checksum: u8,
+    pub computed_global_checksum: u16,
+    pub rom_len: usize,
+}
+
+impl Header {
+    // decodes the header, only failing when a field cannot be understood,
+    // use `validate` to also reject dumps the hardware would not boot
+    pub fn parse(rom: &[u8]) -> Result<Header, HeaderError> {
+        if rom.len() < HEADER_END {
+            return Err(HeaderError::TooSmall { len: rom.len() });
+        }
+
+        let cartridge_type = CartridgeType::from_code(rom[CARTRIDGE_TYPE_ADDRESS]).ok_or(
+            HeaderError::UnknownCartridgeType(rom[CARTRIDGE_TYPE_ADDRESS]),
+        )?;
+        let rom_size_code = rom[ROM_SIZE_ADDRESS];
+        rom_size(rom_size_code).ok_or(HeaderError::UnknownRomSize(rom_size_code))?;
+        let ram_size_code = rom[RAM_SIZE_ADDRESS];
+        ram_size(ram_size_code).ok_or(HeaderError::UnknownRamSize(ram_size_code))?;
+
+        let cgb = match rom[CGB_FLAG_ADDRESS] {
+            0xC0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+        // the title shrank over time: 16 characters, then 15 with the CGB flag,
+        // then 11 followed by a manufacturer code
+        let manufacturer_code = &rom[MANUFACTURER_CODE_ADDRESS..CGB_FLAG_ADDRESS];
+        let has_manufacturer_code = cgb != CgbSupport::None
+            && manufacturer_code
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        let title_end = match (cgb, has_manufacturer_code) {
+            (_, true) => MANUFACTURER_CODE_ADDRESS,
+            (CgbSupport::None, _) => NEW_LICENSEE_ADDRESS,
+            _ => CGB_FLAG_ADDRESS,
+        };
+
+        let licensee = match rom[OLD_LICENSEE_ADDRESS] {
+            USE_NEW_LICENSEE => {
+                Licensee::New(ascii(&rom[NEW_LICENSEE_ADDRESS..NEW_LICENSEE_ADDRESS + 2]))
+            }
+            code => Licensee::Old(code),
+        };
+
+        Ok(Header {
+            title: ascii(&rom[TITLE_ADDRESS..title_end]),
+            manufacturer_code: has_manufacturer_code.then(|| ascii(manufacturer_code)),
+            cgb,
+            sgb: rom[SGB_FLAG_ADDRESS] == 0x03,
+            licensee,
+            cartridge_type,
+            rom_size_code,
+            ram_size_code,
+            version: rom[VERSION_ADDRESS],
+            header_checksum: rom[HEADER_CHECKSUM_ADDRESS],
+            global_checksum: u16::from_be_bytes([
+                rom[GLOBAL_CHECKSUM_ADDRESS],
+                rom[GLOBAL_CHECKSUM_ADDRESS + 1],
+            ]),
+            logo_valid: rom[LOGO_ADDRESS..LOGO_ADDRESS + NINTENDO_LOGO.len()] == NINTENDO_LOGO,
+            computed_header_checksum: header_checksum(rom),
+            computed_global_checksum: global_checksum(rom),
+            rom_len: rom.len(),
+        })
+    }
+
+    // parses the header and rejects what the boot rom would refuse and truncated dumps,
+    // the global checksum is not part of it as the hardware never looks at it
+    pub fn validate(rom: &[u8]) -> Result<Header, HeaderError> {
+        let header = Header::parse(rom)?;
+        if !header.logo_valid {
+            return Err(HeaderError::BadLogo);
+        }
+        if !header.header_checksum_valid() {
+            return Err(HeaderError::BadHeaderChecksum {
+                expected: header.header_checksum,
+                computed: header.computed_header_checksum,
+            });
+        }
+        if header.rom_len < header.rom_size() {
+            return Err(HeaderError::Truncated {
+                expected: header.rom_size(),
+                len: header.rom_len,
+            });
+        }
+        Ok(header)
+    }
+
+    pub fn rom_size(&self) -> usize {
+        rom_size(self.rom_size_code).unwrap_or(0)
+    }
+
+    pub fn ram_size(&self) -> usize {
+        // the MBC2 has 512 half bytes built in and announces no ram
+        match self.cartridge_type.mapper {
+            Mapper::Mbc2 => 0x200,
+            _ => ram_size(self.ram_size_code).unwrap_or(0),
+        }
+    }
+
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    // every check passed, including the global checksum
+    pub fn is_valid(&self) -> bool {
+        self.logo_valid
+            && self.header_checksum_valid()
+            && self.global_checksum_valid()
+            && self.rom_len >= self.rom_size()
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let check = |valid: bool| if valid { "ok" } else { "BAD" };
+        writeln!(f, "title            {}", self.title)?;
+        if let Some(code) = &self.manufacturer_code {
+            writeln!(f, "manufacturer     {}", code)?;
+        }
+        writeln!(f, "cgb              {:?}", self.cgb)?;
+        writeln!(f, "sgb              {}", self.sgb)?;
+        match &self.licensee {
+            Licensee::Old(code) => writeln!(f, "licensee         {:#04X}", code)?,
+            Licensee::New(code) => writeln!(f, "licensee         {}", code)?,
+        }
+        writeln!(f, "type             {}", self.cartridge_type)?;
+        writeln!(
+            f,
+            "rom size         {}KB ({:#04X}), dump is {}KB",
+            self.rom_size() / 1024,
+            self.rom_size_code,
+            self.rom_len / 1024
+        )?;
+        writeln!(
+            f,
+            "ram size         {}KB ({:#04X})",
+            self.ram_size() / 1024,
+            self.ram_size_code
+        )?;
+        writeln!(f, "version          {}", self.version)?;
+        writeln!(f, "logo             {}", check(self.logo_valid))?;
+        writeln!(
+            f,
+            "header checksum  {:#04X} {} (computed {:#04X})",
+            self.header_checksum,
+            check(self.header_checksum_valid()),
+            self.computed_header_checksum
+        )?;
+        writeln!(
+            f,
+            "global checksum  {:#06X} {} (computed {:#06X})",
+            self.global_checksum,
+            check(self.global_checksum_valid()),
+            self.computed_global_checksum
+        )
+    }
+}
+
+// 32KB shifted by the code, plus a few sizes only seen in unofficial documents
+fn rom_size(code: u8) -> Option<usize> {
+    match code {
+        0x00..=0x08 => Some((2 * ROM_BANK_SIZE) << code),
+        0x52 => Some(72 * ROM_BANK_SIZE),
+        0x53 => Some(80 * ROM_BANK_SIZE),
+        0x54 => Some(96 * ROM_BANK_SIZE),
+        _ => None,
+    }
+}
+
+fn ram_size(code: u8) -> Option<usize> {
+    match code {
+        0x00 => Some(0),
+        // listed in some documents but never used by a licensed game
+        0x01 => Some(0x800),
+        0x02 => Some(RAM_BANK_SIZE),
+        0x03 => Some(4 * RAM_BANK_SIZE),
+        0x04 => Some(16 * RAM_BANK_SIZE),
+        0x05 => Some(8 * RAM_BANK_SIZE),
+        _ => None,
+    }
+}
+
+// checked by the boot rom over 0x0134-0x014C
+fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_ADDRESS..HEADER_CHECKSUM_ADDRESS]
+        .iter()
+        .fold(0u8, |x, byte| x.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+// sum of every byte of the rom but the checksum itself
+fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| !(GLOBAL_CHECKSUM_ADDRESS..HEADER_END).contains(address))
+        .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+}
+
+// stops at the first NUL, other non printable characters are replaced
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| {
+            if c.is_ascii_graphic() || *c == b' ' {
+                *c as char
+            } else {
+                '?'
+            }
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+// smallest image passing every check the boot rom does, with the sizes announced
+#[cfg(test)]
+pub(crate) fn test_rom(cartridge_type: u8, rom_size_code: u8, ram_size_code: u8) -> Vec<u8> {
+    let mut rom = vec![0; rom_size(rom_size_code).unwrap()];
+    rom[LOGO_ADDRESS..LOGO_ADDRESS + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+    rom[CARTRIDGE_TYPE_ADDRESS] = cartridge_type;
+    rom[ROM_SIZE_ADDRESS] = rom_size_code;
+    rom[RAM_SIZE_ADDRESS] = ram_size_code;
+    rom[HEADER_CHECKSUM_ADDRESS] = header_checksum(&rom);
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_well_formed_header_is_accepted() {
+        let header = Header::validate(&test_rom(0x03, 0x02, 0x03)).unwrap();
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc1);
+        assert!(header.cartridge_type.battery);
+        assert_eq!(header.rom_size(), 128 * 1024);
+        assert_eq!(header.ram_size(), 32 * 1024);
+    }
+
+    #[test]
+    fn a_bad_logo_is_rejected() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[LOGO_ADDRESS] ^= 0xFF;
+        assert_eq!(Header::validate(&rom).unwrap_err(), HeaderError::BadLogo);
+    }
+
+    #[test]
+    fn a_bad_header_checksum_is_rejected() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        let computed = rom[HEADER_CHECKSUM_ADDRESS];
+        rom[HEADER_CHECKSUM_ADDRESS] = computed.wrapping_add(1);
+        assert_eq!(
+            Header::validate(&rom).unwrap_err(),
+            HeaderError::BadHeaderChecksum {
+                expected: computed.wrapping_add(1),
+                computed,
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_images_are_rejected() {
+        let mut rom = test_rom(0x01, 0x01, 0x00);
+        rom.truncate(0x4000);
+        assert_eq!(
+            Header::validate(&rom).unwrap_err(),
+            HeaderError::Truncated {
+                expected: 0x10000,
+                len: 0x4000,
+            }
+        );
+
+        rom.truncate(HEADER_END - 1);
+        assert_eq!(
+            Header::validate(&rom).unwrap_err(),
+            HeaderError::TooSmall {
+                len: HEADER_END - 1
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_codes_are_rejected() {
+        assert_eq!(
+            Header::validate(&test_rom(0x04, 0x00, 0x00)).unwrap_err(),
+            HeaderError::UnknownCartridgeType(0x04)
+        );
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[RAM_SIZE_ADDRESS] = 0x06;
+        assert_eq!(
+            Header::parse(&rom).unwrap_err(),
+            HeaderError::UnknownRamSize(0x06)
+        );
+    }
+
+    #[test]
+    fn the_mapper_and_ram_follow_the_cartridge_type() {
+        let cases = [
+            // (type, ram size code, mapper, ram size)
+            (0x00, 0x00, Mapper::None, 0),
+            (0x08, 0x02, Mapper::None, 0x2000),
+            (0x01, 0x00, Mapper::Mbc1, 0),
+            (0x03, 0x03, Mapper::Mbc1, 0x8000),
+            // the MBC2 ram is built in, the header announces none
+            (0x05, 0x00, Mapper::Mbc2, 0x200),
+            (0x06, 0x00, Mapper::Mbc2, 0x200),
+            (0x11, 0x00, Mapper::Mbc3, 0),
+            (0x13, 0x03, Mapper::Mbc3, 0x8000),
+            (0x19, 0x00, Mapper::Mbc5, 0),
+            (0x1B, 0x04, Mapper::Mbc5, 0x20000),
+            (0x1E, 0x05, Mapper::Mbc5, 0x10000),
+            (0xFF, 0x01, Mapper::Huc1, 0x800),
+        ];
+        for (code, ram_size_code, mapper, ram_size) in cases {
+            let header = Header::validate(&test_rom(code, 0x00, ram_size_code)).unwrap();
+            assert_eq!(header.cartridge_type.mapper, mapper, "type {:#04X}", code);
+            assert_eq!(header.ram_size(), ram_size, "type {:#04X}", code);
+        }
+
+        let timer = CartridgeType::from_code(0x10).unwrap();
+        assert!(timer.timer && timer.ram && timer.battery);
+        assert!(CartridgeType::from_code(0x1C).unwrap().rumble);
+    }
+}
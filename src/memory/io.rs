@@ -0,0 +1,109 @@
+// hardware triggered by an access to an IO register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    // plain storage
+    None,
+    // DIV, TIMA, TMA and TAC live in the timer
+    Timer,
+    // writing starts an OAM DMA
+    Dma,
+    // LY is driven by the ppu
+    Ppu,
+    // writing a non zero value unmaps the boot rom
+    BootRom,
+}
+
+// describes one register of 0xFF00-0xFF7F
+// bits outside `read_mask` always read as 1 (unused or write-only bits),
+// bits outside `write_mask` are left untouched by writes (unused or read-only bits)
+#[derive(Clone, Copy, Debug)]
+pub struct IoRegister {
+    pub name: &'static str,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    pub effect: Effect,
+}
+
+const fn register(name: &'static str, read_mask: u8, write_mask: u8) -> Option<IoRegister> {
+    Some(IoRegister {
+        name,
+        read_mask,
+        write_mask,
+        effect: Effect::None,
+    })
+}
+
+const fn register_with_effect(
+    name: &'static str,
+    read_mask: u8,
+    write_mask: u8,
+    effect: Effect,
+) -> Option<IoRegister> {
+    Some(IoRegister {
+        name,
+        read_mask,
+        write_mask,
+        effect,
+    })
+}
+
+// DMG registers indexed by address - 0xFF00, unmapped addresses (None) read 0xFF and
+// ignore writes. CGB only registers are not emulated and read as unmapped.
+pub const IO_REGISTERS: [Option<IoRegister>; 0x80] = {
+    let mut table = [None; 0x80];
+    // joypad: no button is ever pressed so the input bits read 1
+    table[0x00] = register("P1", 0x30, 0x30);
+    // serial
+    table[0x01] = register("SB", 0xFF, 0xFF);
+    table[0x02] = register("SC", 0x81, 0x81);
+    // timer
+    table[0x04] = register_with_effect("DIV", 0xFF, 0xFF, Effect::Timer);
+    table[0x05] = register_with_effect("TIMA", 0xFF, 0xFF, Effect::Timer);
+    table[0x06] = register_with_effect("TMA", 0xFF, 0xFF, Effect::Timer);
+    table[0x07] = register_with_effect("TAC", 0x07, 0x07, Effect::Timer);
+    table[0x0F] = register("IF", 0x1F, 0x1F);
+    // sound, the frequency and length bits are write-only
+    table[0x10] = register("NR10", 0x7F, 0x7F);
+    table[0x11] = register("NR11", 0xC0, 0xFF);
+    table[0x12] = register("NR12", 0xFF, 0xFF);
+    table[0x13] = register("NR13", 0x00, 0xFF);
+    table[0x14] = register("NR14", 0x40, 0xC7);
+    table[0x16] = register("NR21", 0xC0, 0xFF);
+    table[0x17] = register("NR22", 0xFF, 0xFF);
+    table[0x18] = register("NR23", 0x00, 0xFF);
+    table[0x19] = register("NR24", 0x40, 0xC7);
+    table[0x1A] = register("NR30", 0x80, 0x80);
+    table[0x1B] = register("NR31", 0x00, 0xFF);
+    table[0x1C] = register("NR32", 0x60, 0x60);
+    table[0x1D] = register("NR33", 0x00, 0xFF);
+    table[0x1E] = register("NR34", 0x40, 0xC7);
+    table[0x20] = register("NR41", 0x00, 0x3F);
+    table[0x21] = register("NR42", 0xFF, 0xFF);
+    table[0x22] = register("NR43", 0xFF, 0xFF);
+    table[0x23] = register("NR44", 0x40, 0xC0);
+    table[0x24] = register("NR50", 0xFF, 0xFF);
+    table[0x25] = register("NR51", 0xFF, 0xFF);
+    // the channel status bits are read-only and stay 0 without an APU
+    table[0x26] = register("NR52", 0x8F, 0x80);
+    let mut wave = 0x30;
+    while wave < 0x40 {
+        table[wave] = register("WAVE", 0xFF, 0xFF);
+        wave += 1;
+    }
+    // lcd, the mode and coincidence bits of STAT and LY belong to the PPU
+    table[0x40] = register("LCDC", 0xFF, 0xFF);
+    table[0x41] = register("STAT", 0x7F, 0x78);
+    table[0x42] = register("SCY", 0xFF, 0xFF);
+    table[0x43] = register("SCX", 0xFF, 0xFF);
+    table[0x44] = register_with_effect("LY", 0xFF, 0x00, Effect::Ppu);
+    table[0x45] = register("LYC", 0xFF, 0xFF);
+    table[0x46] = register_with_effect("DMA", 0xFF, 0xFF, Effect::Dma);
+    table[0x47] = register("BGP", 0xFF, 0xFF);
+    table[0x48] = register("OBP0", 0xFF, 0xFF);
+    table[0x49] = register("OBP1", 0xFF, 0xFF);
+    table[0x4A] = register("WY", 0xFF, 0xFF);
+    table[0x4B] = register("WX", 0xFF, 0xFF);
+    // boot rom
+    table[0x50] = register_with_effect("BANK", 0x00, 0xFF, Effect::BootRom);
+    table
+};
@@ -1,50 +1,530 @@
+mod cartridge;
+mod dma;
+mod header;
+mod io;
+mod ppu;
+
 use std::cmp::min;
+use std::fmt;
+
+pub use self::cartridge::{Cartridge, Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+pub use self::dma::{Dma, DMA_ADDRESS, OAM_SIZE};
+pub use self::header::{
+    CartridgeType, CgbSupport, Header, HeaderError, Licensee, HEADER_CHECKSUM_ADDRESS, HEADER_END,
+    LOGO_ADDRESS, NINTENDO_LOGO,
+};
+pub use self::io::{Effect, IoRegister, IO_REGISTERS};
+pub use self::ppu::{Ppu, LCDC_ADDRESS, LY_ADDRESS};
+use crate::bus::Bus;
+use crate::interrupts::{Interrupt, IE_ADDRESS};
+use crate::model::Model;
+use crate::timer::{Timer, DIV_ADDRESS, TAC_ADDRESS};
+
+// the whole 16 bits address space, including the IE register at 0xFFFF
+const MEMORY_SIZE: usize = 0x10000;
+pub const ROM: usize = 0x0000;
+pub const VRAM: usize = 0x8000;
+pub const EXTERNAL_RAM: usize = 0xA000;
+pub const RAM: usize = 0xC000;
+pub const ECHO_RAM: usize = 0xE000;
+pub const OAM_RAM: usize = 0xFE00;
+pub const UNUSABLE: usize = 0xFEA0;
+pub const IO: usize = 0xFF00;
+pub const HRAM: usize = 0xFF80;
+
+const VRAM_SIZE: usize = 0x2000;
+const RAM_SIZE: usize = 0x2000;
+const IO_SIZE: usize = 0x80;
+const HRAM_SIZE: usize = 0x7F;
 
-const MEMORY_SIZE: usize = 0xFFFF;
-const ROM: usize = 0x0000;
-const VRAM: usize = 0x8000;
-const EXTERNAL_RAM: usize = 0xA000;
-const RAM: usize = 0xC000;
-const OAM_RAM: usize = 0xFE00;
-const IO: usize = 0xFF00;
-const HRAM: usize = 0xFF80;
+// writing a non zero value there unmaps the boot rom
+pub const BOOT_ROM_DISABLE_ADDRESS: usize = 0xFF50;
+// the DMG boot rom covers 0x0000-0x00FF, the CGB one also covers 0x0200-0x08FF
+// leaving the cartridge header visible in between
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
 
+// memory management unit, routes every address of the cpu bus to the component owning it
 #[derive(Clone)]
 pub struct Memory {
-    pub mem_space: [u8; MEMORY_SIZE],
+    pub cartridge: Cartridge,
+    // mapped over the cartridge rom until 0xFF50 is written
+    boot_rom: Option<Vec<u8>>,
+    vram: [u8; VRAM_SIZE],
+    ram: [u8; RAM_SIZE],
+    oam: [u8; OAM_SIZE],
+    // registers that are not owned by a component are plain storage, see IO_REGISTERS
+    io: [u8; IO_SIZE],
+    hram: [u8; HRAM_SIZE],
+    ie: u8,
+    pub timer: Timer,
+    pub ppu: Ppu,
+    dma: Option<Dma>,
+    // some unmapped areas read differently depending on the hardware
+    pub model: Model,
+    // LY always reads 0x90 like in the emulator Gameboy Doctor logs come from,
+    // so that traces of code waiting for VBlank match them
+    pub doctor: bool,
 }
 
 impl Memory {
     pub fn new() -> Memory {
-
-        let mem_space = [0; MEMORY_SIZE];
-
-        return Memory {
-            mem_space: mem_space,
+        Memory {
+            cartridge: Cartridge::default(),
+            boot_rom: None,
+            vram: [0; VRAM_SIZE],
+            ram: [0; RAM_SIZE],
+            oam: [0; OAM_SIZE],
+            io: [0; IO_SIZE],
+            hram: [0; HRAM_SIZE],
+            ie: 0,
+            timer: Timer::new(),
+            ppu: Ppu::new(),
+            dma: None,
+            model: Model::Dmg,
+            doctor: false,
         }
     }
 
     pub fn read_bytes(&self, pointer: usize) -> u8 {
-        return self.mem_space[pointer];
+        match pointer {
+            ROM..=0x08FF if self.boot_rom_covers(pointer) => self.read_boot_rom(pointer),
+            ROM..=0x7FFF => self.cartridge.read_rom(pointer),
+            VRAM..=0x9FFF => self.vram[pointer - VRAM],
+            EXTERNAL_RAM..=0xBFFF => self.cartridge.read_ram(pointer),
+            RAM..=0xDFFF => self.ram[pointer - RAM],
+            // mirror of 0xC000-0xDDFF
+            ECHO_RAM..=0xFDFF => self.ram[pointer - ECHO_RAM],
+            // OAM and the area after it are locked while a DMA copies to OAM
+            OAM_RAM..=0xFEFF if self.dma_copying() => 0xFF,
+            OAM_RAM..=0xFE9F => self.oam[pointer - OAM_RAM],
+            UNUSABLE..=0xFEFF => self.read_unusable(pointer),
+            IO..=0xFF7F => self.read_io(pointer),
+            HRAM..=0xFFFE => self.hram[pointer - HRAM],
+            IE_ADDRESS => self.ie,
+            _ => 0xFF,
+        }
     }
 
     pub fn write_bytes(&mut self, pointer: usize, data: u8) {
-        if pointer < MEMORY_SIZE {
-            self.mem_space[pointer] = data;
+        match pointer {
+            ROM..=0x7FFF => self.cartridge.write_rom(pointer, data),
+            VRAM..=0x9FFF => self.vram[pointer - VRAM] = data,
+            EXTERNAL_RAM..=0xBFFF => self.cartridge.write_ram(pointer, data),
+            RAM..=0xDFFF => self.ram[pointer - RAM] = data,
+            ECHO_RAM..=0xFDFF => self.ram[pointer - ECHO_RAM] = data,
+            OAM_RAM..=0xFE9F if self.dma_copying() => {}
+            OAM_RAM..=0xFE9F => self.oam[pointer - OAM_RAM] = data,
+            IO..=0xFF7F => self.write_io(pointer, data),
+            HRAM..=0xFFFE => self.hram[pointer - HRAM] = data,
+            IE_ADDRESS => self.ie = data,
+            _ => {}
+        }
+    }
+
+    // writes to 0xFEA0-0xFEFF are ignored, reads depend on the model
+    // CGB revisions 0 to D have a small RAM there, only revision E is modeled, see Model::Cgb
+    fn read_unusable(&self, pointer: usize) -> u8 {
+        match self.model {
+            // unverified for the AGB, it reads like the DMG until tested on hardware
+            Model::Dmg0 | Model::Dmg | Model::Mgb | Model::Sgb | Model::Sgb2 | Model::Agb => 0x00,
+            // the high nibble of the low address byte twice, 0xFEA5 reads 0xAA
+            Model::Cgb => {
+                let nibble = (pointer as u8) & 0xF0;
+                nibble | nibble >> 4
+            }
+        }
+    }
+
+    fn read_io(&self, pointer: usize) -> u8 {
+        let register = match IO_REGISTERS[pointer - IO] {
+            Some(register) => register,
+            None => return 0xFF,
+        };
+        let value = match register.effect {
+            Effect::Timer => self.timer.read(pointer),
+            Effect::Ppu if self.doctor => 0x90,
+            Effect::Ppu => self.ppu.ly,
+            Effect::None | Effect::Dma | Effect::BootRom => self.io[pointer - IO],
+        };
+        value | !register.read_mask
+    }
+
+    fn write_io(&mut self, pointer: usize, data: u8) {
+        let register = match IO_REGISTERS[pointer - IO] {
+            Some(register) => register,
+            None => return,
+        };
+        let data = data & register.write_mask;
+        match register.effect {
+            Effect::Timer => self.timer.write(pointer, data),
+            Effect::Dma => {
+                self.io[pointer - IO] = data;
+                self.dma = Some(Dma::new(data));
+            }
+            // only the bus sets LY
+            Effect::Ppu => {}
+            Effect::BootRom => {
+                if data != 0 {
+                    self.boot_rom = None;
+                }
+            }
+            Effect::None => {
+                let stored = &mut self.io[pointer - IO];
+                *stored = (*stored & !register.write_mask) | data;
+            }
+        }
+    }
+
+    // stores a value without triggering the hardware behind the address nor applying
+    // the write mask, used to restore a known state such as the one left by the boot rom
+    pub fn poke(&mut self, pointer: usize, data: u8) {
+        match pointer {
+            DIV_ADDRESS..=TAC_ADDRESS => self.timer.write(pointer, data),
+            LY_ADDRESS => self.ppu.ly = data,
+            IO..=0xFF7F => self.io[pointer - IO] = data,
+            _ => self.write_bytes(pointer, data),
+        }
+    }
+
+    fn tick_dma(&mut self) {
+        let mut dma = match self.dma {
+            Some(dma) => dma,
+            None => return,
+        };
+
+        match dma.index {
+            // startup delay
+            None => dma.index = Some(0),
+            Some(index) => {
+                // the copy itself is not blocked by the OAM lock
+                let source = dma.source.wrapping_add(index as u16) as usize;
+                self.oam[index] = match source {
+                    OAM_RAM..=0xFFFF => 0xFF,
+                    _ => self.read_bytes(source),
+                };
+                dma.index = Some(index + 1);
+            }
+        }
+
+        self.dma = match dma.index {
+            Some(OAM_SIZE) => None,
+            _ => Some(dma),
+        };
+    }
+
+    pub fn dma_copying(&self) -> bool {
+        self.dma.is_some_and(|dma| dma.is_copying())
+    }
+
+    // maps a DMG (256 bytes) or CGB (2304 bytes) boot rom over the cartridge
+    pub fn load_boot_rom(&mut self, bytes: &[u8]) {
+        self.boot_rom = Some(bytes[..min(bytes.len(), CGB_BOOT_ROM_SIZE)].to_vec());
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    // hands the whole rom area over to the cartridge, there is no way back
+    pub fn unmap_boot_rom(&mut self) {
+        self.boot_rom = None;
+    }
+
+    fn boot_rom_covers(&self, pointer: usize) -> bool {
+        match &self.boot_rom {
+            Some(boot_rom) if boot_rom.len() > DMG_BOOT_ROM_SIZE => {
+                pointer < DMG_BOOT_ROM_SIZE || (0x0200..CGB_BOOT_ROM_SIZE).contains(&pointer)
+            }
+            Some(_) => pointer < DMG_BOOT_ROM_SIZE,
+            None => false,
         }
     }
 
-    pub fn load_rom(&mut self, bytes: &Vec<u8>) {
-        if bytes.len() > VRAM {
-            println!("Weird, ROM does not fit in memory, size > 8Kb")
+    fn read_boot_rom(&self, pointer: usize) -> u8 {
+        let boot_rom = self.boot_rom.as_deref().unwrap_or_default();
+        boot_rom.get(pointer).copied().unwrap_or(0xFF)
+    }
+
+    // replaces the cartridge with a whole rom image, the header selects the mapper
+    pub fn load_cartridge(&mut self, bytes: &[u8]) -> Result<Header, HeaderError> {
+        let (cartridge, header) = Cartridge::from_rom(bytes.to_vec())?;
+        self.cartridge = cartridge;
+        Ok(header)
+    }
+
+    // copies a program over the start of the cartridge rom, for small test programs
+    // the rom area is 32KB, anything past it is dropped, bigger roms need a mapper,
+    // see load_cartridge
+    pub fn load_rom(&mut self, bytes: &[u8]) {
+        let rom = self.cartridge.rom_mut();
+        let size = min(bytes.len(), min(rom.len(), VRAM));
+        rom[..size].copy_from_slice(&bytes[..size]);
+    }
+}
+
+impl Bus for Memory {
+    fn read(&self, address: u16) -> u8 {
+        self.read_bytes(address as usize)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.write_bytes(address as usize, data)
+    }
+
+    fn tick(&mut self) -> bool {
+        if self.timer.tick() {
+            self.request_interrupt(Interrupt::Timer);
+        }
+        let vblank = self.ppu.tick(self.io[LCDC_ADDRESS - IO]);
+        if vblank {
+            self.request_interrupt(Interrupt::VBlank);
         }
+        self.tick_dma();
+        vblank
+    }
 
-        for i in 0..min(bytes.len(), VRAM) {
-            self.mem_space[i] = bytes[i];
+    fn rom_bank(&self, address: u16) -> u16 {
+        match address {
+            0x0000..=0x7FFF => self.cartridge.rom_bank(address as usize) as u16,
+            _ => 0,
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        return format!("Memory | {:#04X?}", self.mem_space);
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mem_space: Vec<u8> = (0..MEMORY_SIZE)
+            .map(|pointer| self.read_bytes(pointer))
+            .collect();
+        write!(f, "Memory | {:#04X?}", mem_space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupts::IF_ADDRESS;
+
+    fn run_dma(memory: &mut Memory, source: u8, cycles: usize) {
+        memory.write_bytes(DMA_ADDRESS, source);
+        for _ in 0..cycles {
+            memory.tick();
+        }
+    }
+
+    #[test]
+    fn ram_regions_keep_what_is_written() {
+        let mut memory = Memory::new();
+        for address in [VRAM, 0x9FFF, RAM, 0xDFFF, OAM_RAM, 0xFE9F, HRAM, 0xFFFE] {
+            memory.write_bytes(address, 0x5A);
+            assert_eq!(memory.read_bytes(address), 0x5A, "{:#06X}", address);
+        }
+        memory.write_bytes(IE_ADDRESS, 0x1F);
+        assert_eq!(memory.read_bytes(IE_ADDRESS), 0x1F);
+    }
+
+    #[test]
+    fn rom_is_read_only_without_a_mapper() {
+        let mut memory = Memory::new();
+        memory.load_rom(&[0x12, 0x34]);
+        memory.write_bytes(0x0000, 0xFF);
+        assert_eq!(memory.read_bytes(0x0000), 0x12);
+        assert_eq!(memory.read_bytes(0x0001), 0x34);
+    }
+
+    #[test]
+    fn programs_longer_than_the_rom_area_are_truncated() {
+        let mut memory = Memory::new();
+        let mut program = vec![0x11; 0x8000];
+        program.push(0x22);
+        memory.load_rom(&program);
+        assert_eq!(memory.read_bytes(0x7FFF), 0x11);
+        assert_eq!(memory.read_bytes(0x8000), 0x00);
+    }
+
+    #[test]
+    fn external_ram_belongs_to_the_cartridge() {
+        // test programs get 8KB of ram
+        let mut memory = Memory::new();
+        memory.write_bytes(0xBFFF, 0x42);
+        assert_eq!(memory.read_bytes(0xBFFF), 0x42);
+        assert_eq!(memory.cartridge.read_ram(0xBFFF), 0x42);
+    }
+
+    #[test]
+    fn dma_copies_160_bytes_to_oam_after_a_startup_delay() {
+        let mut memory = Memory::new();
+        for index in 0..OAM_SIZE {
+            memory.write_bytes(RAM + index, index as u8);
+        }
+        // OAM stays accessible during the startup delay
+        run_dma(&mut memory, 0xC0, 1);
+        assert!(!memory.dma_copying());
+        assert_eq!(memory.read_bytes(OAM_RAM), 0x00);
+
+        // then it is locked until the last byte is copied
+        memory.tick();
+        assert!(memory.dma_copying());
+        assert_eq!(memory.read_bytes(OAM_RAM), 0xFF);
+        assert_eq!(memory.read_bytes(UNUSABLE), 0xFF);
+        // writes are dropped, even to bytes already copied
+        memory.write_bytes(OAM_RAM, 0xAA);
+        for _ in 1..OAM_SIZE {
+            memory.tick();
+        }
+        assert!(!memory.dma_copying());
+        for index in 0..OAM_SIZE {
+            assert_eq!(memory.read_bytes(OAM_RAM + index), index as u8);
+        }
+        assert_eq!(memory.read_bytes(DMA_ADDRESS), 0xC0);
+    }
+
+    #[test]
+    fn dma_from_the_top_of_memory_copies_ff() {
+        let mut memory = Memory::new();
+        run_dma(&mut memory, 0xFE, 1 + OAM_SIZE);
+        assert_eq!(memory.read_bytes(OAM_RAM), 0xFF);
+        assert_eq!(memory.read_bytes(OAM_RAM + OAM_SIZE - 1), 0xFF);
+    }
+
+    #[test]
+    fn vblank_requests_its_interrupt() {
+        let mut memory = Memory::new();
+        memory.write_bytes(LCDC_ADDRESS, 0x80);
+        // 144 lines of 114 M-cycles
+        let edges = (0..144 * 114).filter(|_| memory.tick()).count();
+        assert_eq!(edges, 1);
+        assert_eq!(memory.read_bytes(LY_ADDRESS), 144);
+        assert_eq!(memory.requested_interrupts(), Interrupt::VBlank.bit());
+    }
+
+    #[test]
+    fn echo_ram_mirrors_work_ram_both_ways() {
+        let mut memory = Memory::new();
+        memory.write_bytes(RAM, 0x11);
+        memory.write_bytes(0xDDFF, 0x22);
+        assert_eq!(memory.read_bytes(ECHO_RAM), 0x11);
+        assert_eq!(memory.read_bytes(0xFDFF), 0x22);
+
+        memory.write_bytes(ECHO_RAM + 0x123, 0x33);
+        assert_eq!(memory.read_bytes(RAM + 0x123), 0x33);
+        // 0xDE00-0xDFFF has no mirror, 0xFE00 is OAM
+        memory.write_bytes(0xDE00, 0x44);
+        assert_eq!(memory.read_bytes(OAM_RAM), 0x00);
+    }
+
+    #[test]
+    fn unusable_area_reads_depend_on_the_model() {
+        let mut memory = Memory::new();
+        for model in Model::ALL {
+            memory.model = model;
+            memory.write_bytes(UNUSABLE, 0x12);
+            let expected = match model {
+                Model::Cgb => [0xAA, 0xBB, 0xFF],
+                _ => [0x00, 0x00, 0x00],
+            };
+            let actual = [0xFEA0, 0xFEB3, 0xFEFF].map(|address| memory.read_bytes(address));
+            assert_eq!(actual, expected, "{}", model);
+        }
+    }
+
+    #[test]
+    fn unused_register_bits_read_one() {
+        let mut memory = Memory::new();
+        // STAT bit 7
+        memory.write_bytes(0xFF41, 0x00);
+        assert_eq!(memory.read_bytes(0xFF41), 0x80);
+        // IF bits 5-7
+        memory.write_bytes(IF_ADDRESS, 0x00);
+        assert_eq!(memory.read_bytes(IF_ADDRESS), 0xE0);
+        memory.write_bytes(IF_ADDRESS, 0xFF);
+        assert_eq!(memory.read_bytes(IF_ADDRESS), 0xFF);
+        assert_eq!(memory.requested_interrupts(), 0x1F);
+        // TAC bits 3-7
+        memory.write_bytes(TAC_ADDRESS, 0x05);
+        assert_eq!(memory.read_bytes(TAC_ADDRESS), 0xFD);
+    }
+
+    #[test]
+    fn read_only_bits_ignore_writes() {
+        let mut memory = Memory::new();
+        // the STAT mode bits belong to the ppu
+        memory.write_bytes(0xFF41, 0xFF);
+        assert_eq!(memory.read_bytes(0xFF41), 0xF8);
+        // so does LY
+        memory.ppu.ly = 0x12;
+        memory.write_bytes(LY_ADDRESS, 0x00);
+        assert_eq!(memory.read_bytes(LY_ADDRESS), 0x12);
+        // write-only sound frequency bits read 1
+        memory.write_bytes(0xFF13, 0x00);
+        assert_eq!(memory.read_bytes(0xFF13), 0xFF);
+    }
+
+    #[test]
+    fn writing_div_resets_the_counter() {
+        let mut memory = Memory::new();
+        memory.timer.set_div(0x42);
+        assert_eq!(memory.read_bytes(DIV_ADDRESS), 0x42);
+        memory.write_bytes(DIV_ADDRESS, 0x99);
+        assert_eq!(memory.read_bytes(DIV_ADDRESS), 0x00);
+        for _ in 0..64 {
+            memory.tick();
+        }
+        assert_eq!(memory.read_bytes(DIV_ADDRESS), 0x01);
+    }
+
+    #[test]
+    fn unmapped_io_reads_ff_and_ignores_writes() {
+        let mut memory = Memory::new();
+        for address in [0xFF03, 0xFF08, 0xFF15, 0xFF27, 0xFF4C, 0xFF7F] {
+            memory.write_bytes(address, 0x00);
+            assert_eq!(memory.read_bytes(address), 0xFF, "{:#06X}", address);
+        }
+    }
+
+    #[test]
+    fn writing_ff50_unmaps_the_boot_rom_for_good() {
+        let mut memory = Memory::new();
+        memory.load_rom(&[0x11; 0x200]);
+        memory.load_boot_rom(&[0x31; DMG_BOOT_ROM_SIZE]);
+        assert_eq!(memory.read_bytes(0x0000), 0x31);
+        assert_eq!(memory.read_bytes(0x00FF), 0x31);
+        assert_eq!(memory.read_bytes(0x0100), 0x11);
+
+        // writing 0 does nothing
+        memory.write_bytes(BOOT_ROM_DISABLE_ADDRESS, 0x00);
+        assert!(memory.boot_rom_mapped());
+        memory.write_bytes(BOOT_ROM_DISABLE_ADDRESS, 0x01);
+        assert!(!memory.boot_rom_mapped());
+        assert_eq!(memory.read_bytes(0x0000), 0x11);
+
+        // there is no way back
+        memory.write_bytes(BOOT_ROM_DISABLE_ADDRESS, 0x00);
+        assert!(!memory.boot_rom_mapped());
+        assert_eq!(memory.read_bytes(0x0000), 0x11);
+        assert_eq!(memory.read_bytes(BOOT_ROM_DISABLE_ADDRESS), 0xFF);
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_visible() {
+        let mut memory = Memory::new();
+        memory.load_rom(&[0x11; 0x1000]);
+        memory.load_boot_rom(&[0x31; CGB_BOOT_ROM_SIZE]);
+        assert_eq!(memory.read_bytes(0x00FF), 0x31);
+        assert_eq!(memory.read_bytes(0x0100), 0x11);
+        assert_eq!(memory.read_bytes(0x01FF), 0x11);
+        assert_eq!(memory.read_bytes(0x0200), 0x31);
+        assert_eq!(memory.read_bytes(0x08FF), 0x31);
+        assert_eq!(memory.read_bytes(0x0900), 0x11);
+
+        memory.write_bytes(BOOT_ROM_DISABLE_ADDRESS, 0x11);
+        assert_eq!(memory.read_bytes(0x0200), 0x11);
+    }
+}
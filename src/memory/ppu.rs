@@ -0,0 +1,41 @@
+// LCD control and current scanline registers
+pub const LCDC_ADDRESS: usize = 0xFF40;
+pub const LY_ADDRESS: usize = 0xFF44;
+
+const CYCLES_PER_LINE: u16 = 456;
+const LINES_PER_FRAME: u8 = 154;
+// first line of the vertical blanking period
+const VBLANK_LINE: u8 = 144;
+
+// only the scanline timing for now, nothing is rendered
+// it is enough for code waiting on LY such as the boot rom
+#[derive(Clone, Default)]
+pub struct Ppu {
+    pub ly: u8,
+    // cycles elapsed in the current line
+    dots: u16,
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu::default()
+    }
+
+    // advances the ppu by one M-cycle, returns true when entering VBlank
+    pub fn tick(&mut self, lcdc: u8) -> bool {
+        // the screen is off, LY stays at 0
+        if lcdc & 0x80 == 0 {
+            self.ly = 0;
+            self.dots = 0;
+            return false;
+        }
+
+        self.dots += 4;
+        if self.dots < CYCLES_PER_LINE {
+            return false;
+        }
+        self.dots -= CYCLES_PER_LINE;
+        self.ly = (self.ly + 1) % LINES_PER_FRAME;
+        self.ly == VBLANK_LINE
+    }
+}
@@ -0,0 +1,200 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cpu::{Reg16, Registers};
+use crate::interrupts::{IE_ADDRESS, IF_ADDRESS};
+
+// hardware revisions, they differ by the state left by their boot rom and a few memory quirks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    // CGB revision E, the last one. Revisions 0 to D differ in a few memory quirks
+    // (e.g. RAM at 0xFEA0-0xFEFF) and are not modeled
+    Cgb,
+    // GBA running GB/GBC software, the memory quirks are assumed to match CGB revision E
+    // except for reads of 0xFEA0-0xFEFF, which are unverified
+    Agb,
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [
+        Model::Dmg0,
+        Model::Dmg,
+        Model::Mgb,
+        Model::Sgb,
+        Model::Sgb2,
+        Model::Cgb,
+        Model::Agb,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Sgb2 => "sgb2",
+            Model::Cgb => "cgb",
+            Model::Agb => "agb",
+        }
+    }
+
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    // cpu registers when the boot rom jumps to 0x0100
+    // DMG and MGB leave H and C set unless the header checksum is 0x00
+    // CGB and AGB values are the ones left for cartridges running in CGB mode
+    pub fn post_boot_registers(self, header_checksum: u8) -> Registers {
+        let checksum_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+        let (af, bc, de, hl) = match self {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0100 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF00 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Sgb2 => (0xFF00, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Agb => (0x1100, 0x0100, 0xFF56, 0x000D),
+        };
+
+        let mut registers = Registers::new();
+        registers.set_register_value_u16(Reg16::AF, af);
+        registers.set_register_value_u16(Reg16::BC, bc);
+        registers.set_register_value_u16(Reg16::DE, de);
+        registers.set_register_value_u16(Reg16::HL, hl);
+        registers.sp = 0xFFFE;
+        registers.pc = 0x0100;
+        registers
+    }
+
+    // DIV when the boot rom exits, it depends on how long the boot rom ran
+    // the SGB and CGB values are not documented so the counter starts from 0
+    pub fn post_boot_div(self) -> u8 {
+        match self {
+            Model::Dmg0 => 0x18,
+            Model::Dmg | Model::Mgb => 0xAB,
+            _ => 0x00,
+        }
+    }
+
+    // IO registers (except DIV, owned by the timer) when the boot rom exits
+    // registers whose value is undocumented for a model are left untouched
+    pub fn post_boot_io(self) -> Vec<(usize, u8)> {
+        let sc = if self.is_cgb() { 0x7F } else { 0x7E };
+        let nr52 = if matches!(self, Model::Sgb | Model::Sgb2) {
+            0xF0
+        } else {
+            0xF1
+        };
+        let dma = if self.is_cgb() { 0x00 } else { 0xFF };
+        let mut io = vec![
+            (0xFF00, 0xCF), // P1
+            (0xFF01, 0x00), // SB
+            (0xFF02, sc),   // SC
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (IF_ADDRESS, 0xE1),
+            (0xFF10, 0x80), // NR10
+            (0xFF11, 0xBF), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF13, 0xFF), // NR13
+            (0xFF14, 0xBF), // NR14
+            (0xFF16, 0x3F), // NR21
+            (0xFF17, 0x00), // NR22
+            (0xFF18, 0xFF), // NR23
+            (0xFF19, 0xBF), // NR24
+            (0xFF1A, 0x7F), // NR30
+            (0xFF1B, 0xFF), // NR31
+            (0xFF1C, 0x9F), // NR32
+            (0xFF1D, 0xFF), // NR33
+            (0xFF1E, 0xBF), // NR34
+            (0xFF20, 0xFF), // NR41
+            (0xFF21, 0x00), // NR42
+            (0xFF22, 0x00), // NR43
+            (0xFF23, 0xBF), // NR44
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF26, nr52), // NR52
+            (0xFF40, 0x91), // LCDC
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF45, 0x00), // LYC
+            (0xFF46, dma),  // DMA
+            (0xFF47, 0xFC), // BGP
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+            (IE_ADDRESS, 0x00),
+        ];
+
+        match self {
+            Model::Dmg0 => io.extend([(0xFF41, 0x81), (0xFF44, 0x91)]), // STAT, LY
+            Model::Dmg | Model::Mgb => io.extend([(0xFF41, 0x85), (0xFF44, 0x00)]),
+            _ => {}
+        }
+        io
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Model, String> {
+        Model::ALL
+            .into_iter()
+            .find(|model| model.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown model {}", name))
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dmg_flags_depend_on_the_header_checksum() {
+        let registers = Model::Dmg.post_boot_registers(0x00);
+        assert_eq!(registers.get_register_value_u16(Reg16::AF), 0x0180);
+        let registers = Model::Dmg.post_boot_registers(0x3C);
+        assert_eq!(registers.get_register_value_u16(Reg16::AF), 0x01B0);
+        assert_eq!(registers.get_register_value_u16(Reg16::BC), 0x0013);
+        assert_eq!(registers.get_register_value_u16(Reg16::DE), 0x00D8);
+        assert_eq!(registers.get_register_value_u16(Reg16::HL), 0x014D);
+        assert_eq!(registers.sp, 0xFFFE);
+        assert_eq!(registers.pc, 0x0100);
+    }
+
+    #[test]
+    fn a_identifies_the_hardware() {
+        // A is 0x01 on DMG and SGB, 0xFF on MGB and SGB2, 0x11 on CGB and AGB
+        let a = |model: Model| model.post_boot_registers(0x3C).a;
+        assert_eq!(a(Model::Dmg), 0x01);
+        assert_eq!(a(Model::Sgb), 0x01);
+        assert_eq!(a(Model::Mgb), 0xFF);
+        assert_eq!(a(Model::Sgb2), 0xFF);
+        assert_eq!(a(Model::Cgb), 0x11);
+        assert_eq!(a(Model::Agb), 0x11);
+        // AGB is told apart from CGB by bit 0 of B
+        assert_eq!(Model::Agb.post_boot_registers(0x3C).b, 0x01);
+    }
+
+    #[test]
+    fn models_parse_from_their_name() {
+        for model in Model::ALL {
+            assert_eq!(model.name().parse::<Model>(), Ok(model));
+        }
+        assert_eq!("CGB".parse::<Model>(), Ok(Model::Cgb));
+        assert!("gbc".parse::<Model>().is_err());
+    }
+}
@@ -0,0 +1,180 @@
+// DIV, TIMA, TMA and TAC registers
+pub const DIV_ADDRESS: usize = 0xFF04;
+pub const TIMA_ADDRESS: usize = 0xFF05;
+pub const TMA_ADDRESS: usize = 0xFF06;
+pub const TAC_ADDRESS: usize = 0xFF07;
+
+// DIV is the upper byte of a 16 bits counter incremented every cycle.
+// TIMA is incremented on the falling edge of the counter bit selected by TAC,
+// which is why writing DIV or TAC can increment TIMA on real hardware.
+#[derive(Clone, Default)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // TIMA overflowed during the last M-cycle, it is reloaded from TMA on the next one
+    overflow: bool,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer::default()
+    }
+
+    // sets DIV as if the counter had been running, used when skipping the boot rom
+    pub fn set_div(&mut self, div: u8) {
+        self.counter = (div as u16) << 8;
+    }
+
+    // advances the timer by one M-cycle, returns true when the timer interrupt is requested
+    pub fn tick(&mut self) -> bool {
+        let mut interrupt = false;
+        if self.overflow {
+            self.overflow = false;
+            self.tima = self.tma;
+            interrupt = true;
+        }
+
+        let signal = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_falling_edge(signal);
+        interrupt
+    }
+
+    pub fn read(&self, pointer: usize) -> u8 {
+        match pointer {
+            DIV_ADDRESS => (self.counter >> 8) as u8,
+            TIMA_ADDRESS => self.tima,
+            TMA_ADDRESS => self.tma,
+            TAC_ADDRESS => self.tac | 0xF8,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, pointer: usize, data: u8) {
+        let signal = self.signal();
+        match pointer {
+            // any write resets the whole counter
+            DIV_ADDRESS => self.counter = 0,
+            TIMA_ADDRESS => {
+                // writing TIMA during the reload delay cancels the reload
+                self.tima = data;
+                self.overflow = false;
+            }
+            TMA_ADDRESS => self.tma = data,
+            TAC_ADDRESS => self.tac = data & 0x07,
+            _ => {}
+        }
+        self.detect_falling_edge(signal);
+    }
+
+    // value of the counter bit selected by TAC, masked by the timer enable bit
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0b00 => 9, // 4096 Hz
+            0b01 => 3, // 262144 Hz
+            0b10 => 5, // 65536 Hz
+            _ => 7,    // 16384 Hz
+        };
+        self.tac & 0x04 != 0 && self.counter & (1 << bit) != 0
+    }
+
+    fn detect_falling_edge(&mut self, previous: bool) {
+        if previous && !self.signal() {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            self.overflow = overflow;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // timer counting at 262144 Hz, TIMA increments every 4 M-cycles
+    fn fast_timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDRESS, 0x05);
+        timer
+    }
+
+    #[test]
+    fn div_is_the_upper_byte_of_the_counter() {
+        let mut timer = Timer::new();
+        for _ in 0..63 {
+            timer.tick();
+        }
+        assert_eq!(timer.read(DIV_ADDRESS), 0);
+        timer.tick();
+        assert_eq!(timer.read(DIV_ADDRESS), 1);
+    }
+
+    #[test]
+    fn tima_increments_on_the_falling_edge_of_the_selected_bit() {
+        let mut timer = fast_timer();
+        for _ in 0..3 {
+            timer.tick();
+        }
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+        timer.tick();
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+    }
+
+    #[test]
+    fn disabled_timer_does_not_count() {
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDRESS, 0x01);
+        for _ in 0..16 {
+            timer.tick();
+        }
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+    }
+
+    #[test]
+    fn overflow_reloads_tma_one_cycle_later() {
+        let mut timer = fast_timer();
+        timer.write(TMA_ADDRESS, 0xAB);
+        timer.write(TIMA_ADDRESS, 0xFF);
+        for _ in 0..4 {
+            assert!(!timer.tick());
+        }
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x00);
+        assert!(timer.tick());
+        assert_eq!(timer.read(TIMA_ADDRESS), 0xAB);
+    }
+
+    #[test]
+    fn writing_tima_during_the_reload_delay_cancels_it() {
+        let mut timer = fast_timer();
+        timer.write(TMA_ADDRESS, 0xAB);
+        timer.write(TIMA_ADDRESS, 0xFF);
+        for _ in 0..4 {
+            timer.tick();
+        }
+        timer.write(TIMA_ADDRESS, 0x12);
+        assert!(!timer.tick());
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x12);
+    }
+
+    #[test]
+    fn resetting_div_while_the_selected_bit_is_set_increments_tima() {
+        let mut timer = fast_timer();
+        timer.tick();
+        timer.tick();
+        timer.write(DIV_ADDRESS, 0x42);
+        assert_eq!(timer.read(DIV_ADDRESS), 0);
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+    }
+
+    #[test]
+    fn disabling_the_timer_while_the_selected_bit_is_set_increments_tima() {
+        let mut timer = fast_timer();
+        timer.tick();
+        timer.tick();
+        timer.write(TAC_ADDRESS, 0x01);
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+        assert_eq!(timer.read(TAC_ADDRESS), 0xF9);
+    }
+}
@@ -0,0 +1,232 @@
+// Per instruction test vectors in the SingleStepTests sm83 format
+// (https://github.com/SingleStepTests/sm83).
+//
+// Every json file of tests/assets/sm83 holds an array of tests, each one giving the
+// initial state of the cpu and ram, the expected final state and the bus activity of
+// every M-cycle. The files there are synthetic: a few cases per opcode written by hand
+// in that format, they only check the harness itself and are not taken from the
+// upstream suite.
+//
+// The upstream vectors are run from the directory given by SM83_TESTS_DIR, e.g. the v1
+// directory of a checkout of the repository above; that test is skipped when it is unset.
+use std::{fs, path::Path};
+
+use my_bg_rust::bus::Bus;
+use my_bg_rust::cpu::{BusAccess, BusCycle, Reg16, CPU};
+use serde_json::Value;
+
+const VECTORS_DIRECTORY: &str = "tests/assets/sm83";
+
+// environment variable pointing at the upstream vectors
+const UPSTREAM_VECTORS_VARIABLE: &str = "SM83_TESTS_DIR";
+
+// stop reporting a file after this many failing tests
+const MAX_REPORTED_FAILURES: usize = 10;
+
+// plain 64KB of RAM without any hardware behind it, which is what the vectors expect
+struct FlatBus {
+    ram: Box<[u8; 0x10000]>,
+}
+
+impl Bus for FlatBus {
+    fn read(&self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.ram[address as usize] = data;
+    }
+}
+
+fn field(state: &Value, name: &str) -> u16 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing field {}", name)) as u16
+}
+
+fn setup(state: &Value) -> CPU<FlatBus> {
+    let mut cpu = CPU::with_bus(FlatBus {
+        ram: Box::new([0; 0x10000]),
+    });
+    cpu.bus_log = Some(Vec::new());
+
+    let registers = &mut cpu.registers;
+    registers.a = field(state, "a") as u8;
+    registers.b = field(state, "b") as u8;
+    registers.c = field(state, "c") as u8;
+    registers.d = field(state, "d") as u8;
+    registers.e = field(state, "e") as u8;
+    registers.h = field(state, "h") as u8;
+    registers.l = field(state, "l") as u8;
+    let af = u16::from_be_bytes([registers.a, field(state, "f") as u8]);
+    registers.set_register_value_u16(Reg16::AF, af);
+    registers.set_register_value_u16(Reg16::SP, field(state, "sp"));
+    registers.pc = field(state, "pc");
+    cpu.ime = field(state, "ime") != 0;
+
+    for entry in state["ram"].as_array().expect("missing field ram") {
+        let address = entry[0].as_u64().unwrap() as u16;
+        cpu.memory.write(address, entry[1].as_u64().unwrap() as u8);
+    }
+    if let Some(ie) = state["ie"].as_u64() {
+        cpu.memory.write(0xFFFF, ie as u8);
+    }
+    cpu
+}
+
+// compares the cpu against the expected final state, one line per mismatching field
+fn compare(cpu: &CPU<FlatBus>, expected: &Value, cycles: &[Value]) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let mut check = |name: &str, actual: u16, expected: u16| {
+        if actual != expected {
+            mismatches.push(format!(
+                "{}: expected 0x{:02X}, got 0x{:02X}",
+                name, expected, actual
+            ));
+        }
+    };
+
+    let registers = &cpu.registers;
+    check("a", registers.a as u16, field(expected, "a"));
+    check("b", registers.b as u16, field(expected, "b"));
+    check("c", registers.c as u16, field(expected, "c"));
+    check("d", registers.d as u16, field(expected, "d"));
+    check("e", registers.e as u16, field(expected, "e"));
+    check("f", registers.f.bits() as u16, field(expected, "f"));
+    check("h", registers.h as u16, field(expected, "h"));
+    check("l", registers.l as u16, field(expected, "l"));
+    check(
+        "sp",
+        registers.get_register_value_u16(Reg16::SP),
+        field(expected, "sp"),
+    );
+    check("pc", registers.pc, field(expected, "pc"));
+    check("ime", cpu.ime as u16, field(expected, "ime"));
+    if let Some(ie) = expected["ie"].as_u64() {
+        check("ie", cpu.memory.read(0xFFFF) as u16, ie as u16);
+    }
+
+    for entry in expected["ram"].as_array().expect("missing field ram") {
+        let address = entry[0].as_u64().unwrap() as u16;
+        check(
+            &format!("ram[0x{:04X}]", address),
+            cpu.memory.read(address) as u16,
+            entry[1].as_u64().unwrap() as u16,
+        );
+    }
+
+    let bus_log: &[BusCycle] = cpu.bus_log.as_deref().unwrap_or_default();
+    if bus_log.len() != cycles.len() {
+        mismatches.push(format!(
+            "cycles: expected {} M-cycles, got {}",
+            cycles.len(),
+            bus_log.len()
+        ));
+    }
+    for (index, (actual, expected)) in bus_log.iter().zip(cycles).enumerate() {
+        // internal cycles are listed as null entries or with a "---" activity
+        let activity = expected[2].as_str().unwrap_or("---");
+        let access = match activity.as_bytes() {
+            [b'r', ..] => BusAccess::Read,
+            [_, b'w', ..] => BusAccess::Write,
+            _ => BusAccess::Internal,
+        };
+        if actual.access != access {
+            mismatches.push(format!(
+                "cycle {}: expected {:?}, got {:?} at 0x{:04X}",
+                index, access, actual.access, actual.address
+            ));
+        } else if access != BusAccess::Internal {
+            let address = expected[0].as_u64().unwrap() as u16;
+            let value = expected[1].as_u64().unwrap() as u8;
+            if actual.address != address || actual.value != value {
+                mismatches.push(format!(
+                    "cycle {}: expected {:?} 0x{:02X} at 0x{:04X}, got 0x{:02X} at 0x{:04X}",
+                    index, access, value, address, actual.value, actual.address
+                ));
+            }
+        }
+    }
+    mismatches
+}
+
+fn run_file(path: &Path) -> (usize, usize) {
+    let content = fs::read_to_string(path).expect("could not read the test vectors");
+    let tests: Vec<Value> = serde_json::from_str(&content).expect("invalid test vectors");
+
+    let mut failures = 0;
+    for test in &tests {
+        let name = test["name"].as_str().unwrap_or("unnamed");
+        let mut cpu = setup(&test["initial"]);
+        let mismatches = match cpu.execute_instruction() {
+            Ok(()) => compare(&cpu, &test["final"], test["cycles"].as_array().unwrap()),
+            Err(error) => vec![error.to_string()],
+        };
+
+        if !mismatches.is_empty() {
+            failures += 1;
+            if failures <= MAX_REPORTED_FAILURES {
+                eprintln!("{} [{}]", path.display(), name);
+                for mismatch in mismatches {
+                    eprintln!("    {}", mismatch);
+                }
+            }
+        }
+    }
+    (tests.len(), failures)
+}
+
+// runs every json file of the directory, failing with the list of files that have failures
+fn run_directory(directory: &Path) {
+    let mut paths: Vec<_> = fs::read_dir(directory)
+        .unwrap_or_else(|error| panic!("could not read {}: {}", directory.display(), error))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+
+    let mut total = 0;
+    let mut failed_files = Vec::new();
+    for path in &paths {
+        let (count, failures) = run_file(path);
+        total += count;
+        if failures > 0 {
+            failed_files.push(format!(
+                "{} ({}/{} failed)",
+                path.file_name().unwrap().to_string_lossy(),
+                failures,
+                count
+            ));
+        }
+    }
+
+    assert!(
+        total > 0,
+        "no test vectors found in {}",
+        directory.display()
+    );
+    assert!(
+        failed_files.is_empty(),
+        "failing test vectors: {}",
+        failed_files.join(", ")
+    );
+}
+
+#[test]
+fn sm83_harness_vectors() {
+    run_directory(&Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_DIRECTORY));
+}
+
+#[test]
+fn sm83_upstream_vectors() {
+    match std::env::var_os(UPSTREAM_VECTORS_VARIABLE) {
+        Some(directory) => run_directory(Path::new(&directory)),
+        None => eprintln!(
+            "skipping the upstream sm83 vectors, set {} to their directory to run them",
+            UPSTREAM_VECTORS_VARIABLE
+        ),
+    }
+}